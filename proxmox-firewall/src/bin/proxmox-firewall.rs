@@ -6,7 +6,11 @@ use anyhow::{Context, Error, bail, format_err};
 use pico_args::Arguments;
 
 use proxmox_firewall::config::{FirewallConfig, PveFirewallConfigLoader, PveNftConfigLoader};
-use proxmox_firewall::firewall::Firewall;
+use proxmox_firewall::firewall::{Firewall, NFLOG_GROUP};
+use proxmox_firewall::logger::{
+    FIREWALL_JSON_LOG_FILE, FIREWALL_LOG_FILE, LogEntry, LogFormat, LogWriter,
+};
+use proxmox_firewall::nflog::NflogSocket;
 use proxmox_log as log;
 use proxmox_log::{LevelFilter, Logger};
 use proxmox_nftables::{NftClient, client::NftError};
@@ -19,9 +23,14 @@ USAGE:
 COMMANDS:
   help              Prints this help message.
   skeleton          Prints the firewall rule skeleton as accepted by 'nft -f -'
-  compile           Compile and print firewall rules as accepted by 'nft -j -f -'
+  compile [--no-compress]
+                    Compile and print firewall rules as accepted by 'nft -j -f -', optionally
+                    without merging adjacent rules into set and verdict map lookups
   start             Execute proxmox-firewall service in foreground
   localnet          Print the contents of the management ipset
+  log [--json]      Execute the firewall logger in foreground, writing the packets logged by
+                    the firewall rules to /var/log/pve-firewall.log and, with --json, as JSON
+                    lines to /var/log/pve-firewall.json
 "#;
 
 const RULE_BASE: &str = include_str!("../../resources/proxmox-firewall.nft");
@@ -41,13 +50,13 @@ fn remove_firewall() -> Result<(), std::io::Error> {
     Ok(())
 }
 
-fn create_firewall_instance() -> Result<Firewall, Error> {
-    let config = FirewallConfig::new(&PveFirewallConfigLoader::new(), &PveNftConfigLoader::new())?;
+fn create_firewall_instance(loader: &PveFirewallConfigLoader) -> Result<Firewall, Error> {
+    let config = FirewallConfig::new(loader, &PveNftConfigLoader::new())?;
     Ok(Firewall::new(config))
 }
 
-fn handle_firewall() -> Result<(), Error> {
-    let firewall = create_firewall_instance()?;
+fn handle_firewall(loader: &PveFirewallConfigLoader) -> Result<(), Error> {
+    let firewall = create_firewall_instance(loader)?;
 
     if !firewall.is_enabled() {
         return remove_firewall().with_context(|| "could not remove firewall tables".to_string());
@@ -75,7 +84,7 @@ fn handle_firewall() -> Result<(), Error> {
 fn init_logger(command: Command) -> Result<(), Error> {
     let mut logger = Logger::from_env("PVE_LOG", LevelFilter::WARN);
 
-    if command == Command::Start {
+    if matches!(command, Command::Start | Command::Log) {
         logger = logger.journald();
     } else {
         logger = logger.stderr_pve();
@@ -94,6 +103,9 @@ fn run_firewall() -> Result<(), Error> {
     // we're disabled here without the need to parse the config, avoiding log-spam errors from that
     let force_disable_flag = std::path::Path::new(FORCE_DISABLE_FLAG_FILE);
 
+    // kept across updates, so resolved hostnames are cached between them
+    let loader = PveFirewallConfigLoader::new();
+
     while !term.load(Ordering::Relaxed) {
         if force_disable_flag.exists() {
             if let Err(error) = remove_firewall() {
@@ -105,7 +117,7 @@ fn run_firewall() -> Result<(), Error> {
         }
         let start = Instant::now();
 
-        if let Err(error) = handle_firewall() {
+        if let Err(error) = handle_firewall(&loader) {
             log::error!("error updating firewall rules: {error:#}");
         }
 
@@ -118,6 +130,40 @@ fn run_firewall() -> Result<(), Error> {
     remove_firewall().with_context(|| "Could not remove firewall rules")
 }
 
+fn run_logger(json: bool) -> Result<(), Error> {
+    let term = Arc::new(AtomicBool::new(false));
+
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))?;
+
+    let mut socket = NflogSocket::bind(NFLOG_GROUP)
+        .with_context(|| format!("could not bind to nflog group {NFLOG_GROUP}"))?;
+
+    let mut writers = vec![LogWriter::new(FIREWALL_LOG_FILE, LogFormat::Classic)?];
+
+    if json {
+        writers.push(LogWriter::new(FIREWALL_JSON_LOG_FILE, LogFormat::Json)?);
+    }
+
+    log::info!("reading firewall log messages from nflog group {NFLOG_GROUP}");
+
+    while !term.load(Ordering::Relaxed) {
+        let entries: Vec<LogEntry> = socket
+            .receive()?
+            .iter()
+            .map(LogEntry::from_packet)
+            .collect();
+
+        for writer in &mut writers {
+            if let Err(error) = writer.write_entries(&entries) {
+                log::error!("error writing firewall log: {error:#}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Command {
     Compile,
@@ -125,6 +171,7 @@ pub enum Command {
     Skeleton,
     Start,
     Localnet,
+    Log,
 }
 
 impl std::str::FromStr for Command {
@@ -137,6 +184,7 @@ impl std::str::FromStr for Command {
             "skeleton" => Command::Skeleton,
             "start" => Command::Start,
             "localnet" => Command::Localnet,
+            "log" => Command::Log,
             cmd => {
                 bail!("{cmd} is not a valid command")
             }
@@ -144,7 +192,7 @@ impl std::str::FromStr for Command {
     }
 }
 
-fn run_command(command: Command) -> Result<(), Error> {
+fn run_command(command: Command, mut args: Arguments) -> Result<(), Error> {
     init_logger(command)?;
 
     match command {
@@ -152,7 +200,17 @@ fn run_command(command: Command) -> Result<(), Error> {
             println!("{}", HELP);
         }
         Command::Compile => {
-            let commands = create_firewall_instance()?.full_host_fw()?;
+            let compress_rules = !args.contains("--no-compress");
+
+            let remaining = args.finish();
+
+            if !remaining.is_empty() {
+                bail!("unexpected arguments: {remaining:?}");
+            }
+
+            let commands = create_firewall_instance(&PveFirewallConfigLoader::new())?
+                .with_rule_compression(compress_rules)
+                .full_host_fw()?;
             let json = serde_json::to_string_pretty(&commands)?;
 
             println!("{json}");
@@ -169,6 +227,17 @@ fn run_command(command: Command) -> Result<(), Error> {
                 println!("{ip}");
             }
         }
+        Command::Log => {
+            let json = args.contains("--json");
+
+            let remaining = args.finish();
+
+            if !remaining.is_empty() {
+                bail!("unexpected arguments: {remaining:?}");
+            }
+
+            run_logger(json)?
+        }
     };
 
     Ok(())
@@ -183,7 +252,7 @@ fn main() -> Result<(), Error> {
         .parse();
 
     if let Ok(command) = parsed_command {
-        run_command(command)
+        run_command(command, args)
     } else {
         eprintln!("Invalid command specified!\n{}", HELP);
         std::process::exit(1);
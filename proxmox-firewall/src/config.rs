@@ -1,7 +1,8 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::default::Default;
 use std::fs::{self, DirEntry, File, ReadDir};
-use std::io::{self, BufReader};
+use std::io::{self, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use anyhow::{Context, Error, bail, format_err};
 
@@ -13,13 +14,17 @@ use proxmox_ve_config::firewall::guest::Config as GuestConfig;
 use proxmox_ve_config::firewall::host::Config as HostConfig;
 use proxmox_ve_config::firewall::types::alias::{Alias, AliasScope, RuleAliasName};
 
-use proxmox_ve_config::firewall::types::Ipset;
-use proxmox_ve_config::firewall::types::ipset::{IpsetScope, RuleIpsetName};
+use proxmox_ve_config::firewall::types::ipset::{IpsetEntry, IpsetName, IpsetScope, RuleIpsetName};
+use proxmox_ve_config::firewall::types::rule::Kind;
+use proxmox_ve_config::firewall::types::rule_match::IpAddrMatch;
+use proxmox_ve_config::firewall::types::{Ipset, Rule};
 use proxmox_ve_config::guest::types::Vmid;
+use proxmox_ve_config::guest::vm::NetworkConfig;
 use proxmox_ve_config::guest::{GuestEntry, GuestMap};
 use proxmox_ve_config::host::types::BridgeName;
 
 use proxmox_network_api::{AltnameMapping, get_network_interfaces};
+use proxmox_network_types::ip_address::{Cidr, Ipv4Cidr, Ipv6Cidr};
 use proxmox_nftables::NftClient;
 use proxmox_nftables::command::{CommandOutput, Commands, List, ListOutput};
 use proxmox_nftables::types::ListChain;
@@ -28,6 +33,8 @@ use proxmox_ve_config::sdn::{
     ipam::{Ipam, IpamJson},
 };
 
+use crate::resolver::{CachingResolver, HostnameResolver, SystemResolver};
+
 pub trait FirewallConfigLoader {
     fn cluster(&self) -> Result<Option<Box<dyn io::BufRead>>, Error>;
     fn host(&self) -> Result<Option<Box<dyn io::BufRead>>, Error>;
@@ -46,10 +53,15 @@ pub trait FirewallConfigLoader {
         bridge_name: &BridgeName,
     ) -> Result<Option<Box<dyn io::BufRead>>, Error>;
     fn interface_mapping(&self) -> Result<AltnameMapping, Error>;
+    fn ipset_file_list(&self) -> Result<Vec<String>, Error>;
+    fn ipset_file(&self, file_name: &str) -> Result<Option<Box<dyn io::BufRead>>, Error>;
+    fn resolve_hostname(&self, hostname: &str) -> Result<Vec<IpAddr>, Error>;
 }
 
 #[derive(Default)]
-pub struct PveFirewallConfigLoader {}
+pub struct PveFirewallConfigLoader {
+    resolver: CachingResolver<SystemResolver>,
+}
 
 impl PveFirewallConfigLoader {
     pub fn new() -> Self {
@@ -88,6 +100,125 @@ fn open_config_folder(path: &str) -> Result<Option<ReadDir>, Error> {
     }
 }
 
+/// checks whether an ipset entry is a fully qualified hostname that needs to be resolved
+fn is_hostname(entry: &str) -> bool {
+    entry.contains('.')
+        && entry.contains(|c: char| c.is_ascii_alphabetic())
+        && entry.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// checks whether a name can be used as the name of a datacenter ipset
+fn is_valid_ipset_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// returns the tags from the raw configuration of a guest
+///
+/// This is a stopgap until proxmox-ve-config exposes the tags of a guest. It follows the format
+/// used by PVE, where tags are separated by `;`, `,` or whitespace.
+fn parse_guest_tags(raw_config: &str) -> Vec<String> {
+    raw_config
+        .lines()
+        .take_while(|line| !line.starts_with('['))
+        .find_map(|line| line.strip_prefix("tags:"))
+        .map(|tags| {
+            tags.split(|c: char| c == ';' || c == ',' || c.is_whitespace())
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// returns the addresses configured via cloud-init (`ipconfigN`) in the raw configuration of a VM
+///
+/// Dynamic configurations, like `ip=dhcp` or `ip6=auto`, are skipped.
+fn parse_cloudinit_addresses(raw_config: &str) -> Vec<Cidr> {
+    raw_config
+        .lines()
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+
+            key.strip_prefix("ipconfig")
+                .filter(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+                .map(|_| value.trim())
+        })
+        .flat_map(|value| value.split(','))
+        .filter_map(|property| {
+            let (key, value) = property.split_once('=')?;
+            let address = value.split_once('/').map_or(value, |(address, _)| address);
+
+            match key.trim() {
+                "ip" => address
+                    .parse::<Ipv4Addr>()
+                    .ok()
+                    .map(|ip| Cidr::from(Ipv4Cidr::from(ip))),
+                "ip6" => address
+                    .parse::<Ipv6Addr>()
+                    .ok()
+                    .map(|ip6| Cidr::from(Ipv6Cidr::from(ip6))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+const GUEST_IPSET_PREFIX: &str = "guests-";
+const GUEST_TAG_IPSET_PREFIX: &str = "guests-tag-";
+
+/// checks whether an ipset name refers to an ipset generated from the guest network configuration
+fn is_guest_network_ipset_name(name: &str) -> bool {
+    if let Some(tag) = name.strip_prefix(GUEST_TAG_IPSET_PREFIX) {
+        return is_valid_ipset_name(tag);
+    }
+
+    name.strip_prefix(GUEST_IPSET_PREFIX)
+        .is_some_and(|vmid| vmid.parse::<Vmid>().is_ok())
+}
+
+/// collects the names of the generated guest ipsets referenced by the given rules
+fn collect_guest_network_ipset_references<'a>(
+    rules: impl IntoIterator<Item = &'a Rule>,
+    references: &mut BTreeSet<String>,
+) {
+    for rule in rules {
+        let Kind::Match(rule_match) = rule.kind() else {
+            continue;
+        };
+
+        let Some(ip) = rule_match.ip() else {
+            continue;
+        };
+
+        for address in [ip.src(), ip.dst()].into_iter().flatten() {
+            let IpAddrMatch::Set(ipset_name) = address else {
+                continue;
+            };
+
+            let name: &str = match ipset_name {
+                RuleIpsetName::Scoped(ipset_name) => match ipset_name.scope() {
+                    IpsetScope::Datacenter => ipset_name.name(),
+                    _ => continue,
+                },
+                RuleIpsetName::Legacy(ipset_name) => ipset_name.as_ref(),
+            };
+
+            if is_guest_network_ipset_name(name) {
+                references.insert(name.to_string());
+            }
+        }
+    }
+}
+
 fn fw_name(dir_entry: DirEntry) -> Option<String> {
     dir_entry
         .file_name()
@@ -100,6 +231,8 @@ const CLUSTER_CONFIG_PATH: &str = "/etc/pve/firewall/cluster.fw";
 const HOST_CONFIG_PATH: &str = "/etc/pve/local/host.fw";
 const BRIDGE_CONFIG_PATH: &str = "/etc/pve/sdn/firewall";
 
+const IPSET_FILE_PATH: &str = "/etc/proxmox-firewall/ipsets";
+
 const SDN_RUNNING_CONFIG_PATH: &str = "/etc/pve/sdn/.running-config";
 const SDN_IPAM_PATH: &str = "/etc/pve/sdn/pve-ipam-state.json";
 const SDN_IPAM_PATH_LEGACY: &str = "/etc/pve/priv/ipam.db"; // TODO: remove with PVE 9+
@@ -233,6 +366,37 @@ impl FirewallConfigLoader for PveFirewallConfigLoader {
             get_network_interfaces()?.into_values(),
         ))
     }
+
+    fn ipset_file_list(&self) -> Result<Vec<String>, Error> {
+        let mut file_names = Vec::new();
+
+        if let Some(files) = open_config_folder(IPSET_FILE_PATH)? {
+            for file in files {
+                if let Some(file_name) = file?.file_name().to_str() {
+                    file_names.push(file_name.to_string());
+                }
+            }
+        }
+
+        Ok(file_names)
+    }
+
+    fn ipset_file(&self, file_name: &str) -> Result<Option<Box<dyn io::BufRead>>, Error> {
+        log::info!("loading ipset file {file_name}");
+
+        let fd = open_config_file(&format!("{IPSET_FILE_PATH}/{file_name}"))?;
+
+        if let Some(file) = fd {
+            let buf_reader = Box::new(BufReader::new(file)) as Box<dyn io::BufRead>;
+            return Ok(Some(buf_reader));
+        }
+
+        Ok(None)
+    }
+
+    fn resolve_hostname(&self, hostname: &str) -> Result<Vec<IpAddr>, Error> {
+        self.resolver.resolve(hostname)
+    }
 }
 
 pub trait NftConfigLoader {
@@ -289,6 +453,9 @@ impl FirewallIpamConfig {
     }
 }
 
+/// Maximum size of a file backing an ipset, larger files are ignored.
+const MAX_IPSET_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
 pub struct FirewallConfig {
     cluster_config: ClusterConfig,
     host_config: HostConfig,
@@ -297,6 +464,10 @@ pub struct FirewallConfig {
     nft_config: BTreeMap<String, ListChain>,
     sdn_config: Option<FirewallSdnConfig>,
     ipam_config: Option<FirewallIpamConfig>,
+    guest_network_ipsets: BTreeMap<String, Ipset>,
+    cluster_table_references: BTreeSet<String>,
+    guest_table_references: BTreeSet<String>,
+    file_ipsets: BTreeMap<String, Ipset>,
     interface_mapping: AltnameMapping,
 }
 
@@ -399,6 +570,282 @@ impl FirewallConfig {
         })
     }
 
+    /// Generates datacenter ipsets from the network configuration of the guests in the cluster.
+    ///
+    /// Rules can reference an ipset `guests-<vmid>` per guest and an ipset `guests-tag-<tag>` per
+    /// guest tag, containing the `ip` and `ip6` addresses as well as the cloud-init addresses of
+    /// the respective guests. They are placed in the datacenter scope (e.g. `+dc/guests-101`),
+    /// since rules can only reference the ipset scopes known to the rule parser. Only the ipsets
+    /// in `references` are generated, but always, even if no guest contributes an address, so that
+    /// the rules referencing them stay valid. Ipsets defined in the cluster configuration take
+    /// precedence over the generated ones.
+    pub fn generate_guest_network_ipsets(
+        firewall_loader: &dyn FirewallConfigLoader,
+        cluster_config: &ClusterConfig,
+        references: &BTreeSet<String>,
+    ) -> Result<BTreeMap<String, Ipset>, Error> {
+        let mut ipset_addresses: BTreeMap<String, Vec<Cidr>> = BTreeMap::new();
+
+        for name in references {
+            if cluster_config.ipsets().contains_key(name) {
+                log::warn!("ipset dc/{name} is defined in the cluster config, not generating it");
+                continue;
+            }
+
+            ipset_addresses.insert(name.clone(), Vec::new());
+        }
+
+        if ipset_addresses.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let needs_tags = ipset_addresses
+            .keys()
+            .any(|name| name.starts_with(GUEST_TAG_IPSET_PREFIX));
+
+        let guest_list = firewall_loader.guest_list()?;
+
+        let mut guests: Vec<_> = guest_list.iter().collect();
+        guests.sort_by_key(|(vmid, _)| **vmid);
+
+        for (vmid, entry) in guests {
+            let vmid_ipset = format!("{GUEST_IPSET_PREFIX}{vmid}");
+
+            if !needs_tags && !ipset_addresses.contains_key(&vmid_ipset) {
+                continue;
+            }
+
+            let raw_config = match firewall_loader.guest_config(vmid, entry) {
+                Ok(Some(mut raw_config)) => {
+                    let mut buffer = String::new();
+
+                    if let Err(err) = raw_config.read_to_string(&mut buffer) {
+                        log::warn!("could not read guest config for #{vmid}: {err}");
+                        continue;
+                    }
+
+                    buffer
+                }
+                Ok(None) => continue,
+                Err(err) => {
+                    log::warn!("could not load guest config for #{vmid}: {err:#}");
+                    continue;
+                }
+            };
+
+            let mut addresses: Vec<Cidr> = Vec::new();
+
+            match NetworkConfig::parse(raw_config.as_bytes()) {
+                Ok(network_config) => {
+                    for network_device in network_config.network_devices().values() {
+                        addresses.extend(
+                            network_device
+                                .ip()
+                                .map(|ip| Cidr::from(Ipv4Cidr::from(*ip.address()))),
+                        );
+                        addresses.extend(
+                            network_device
+                                .ip6()
+                                .map(|ip6| Cidr::from(Ipv6Cidr::from(*ip6.address()))),
+                        );
+                    }
+                }
+                Err(err) => {
+                    log::warn!("could not parse network config of guest #{vmid}: {err:#}");
+                }
+            }
+
+            addresses.extend(parse_cloudinit_addresses(&raw_config));
+
+            let mut names = vec![vmid_ipset];
+
+            for tag in parse_guest_tags(&raw_config) {
+                if is_valid_ipset_name(&tag) {
+                    names.push(format!("{GUEST_TAG_IPSET_PREFIX}{tag}"));
+                } else {
+                    log::debug!("tag {tag} of guest #{vmid} is not a valid ipset name, skipping");
+                }
+            }
+
+            for name in names {
+                let Some(ipset_addresses) = ipset_addresses.get_mut(&name) else {
+                    continue;
+                };
+
+                for address in &addresses {
+                    if !ipset_addresses.contains(address) {
+                        ipset_addresses.push(*address);
+                    }
+                }
+            }
+        }
+
+        let mut ipsets = BTreeMap::new();
+
+        for (name, addresses) in ipset_addresses {
+            let mut ipset = Ipset::new(IpsetName::new(IpsetScope::Datacenter, name.clone()));
+
+            for address in addresses {
+                ipset.push(IpsetEntry::from(address));
+            }
+
+            ipsets.insert(name, ipset);
+        }
+
+        Ok(ipsets)
+    }
+
+    /// Loads the datacenter ipsets backed by files.
+    ///
+    /// Files are either named `<ipset>.list`, containing one CIDR per line, or `<ipset>.json`,
+    /// containing an array of CIDRs. Entries can also be fully qualified hostnames, resolved via
+    /// [`FirewallConfigLoader::resolve_hostname`]. Entries prefixed with `!` are added as nomatch
+    /// entries. Invalid entries are skipped, files that cannot be read, are not valid UTF-8 or
+    /// exceed [`MAX_IPSET_FILE_SIZE`] are ignored. The file name has to be a valid ipset name,
+    /// consisting of `A-Z`, `a-z`, `0-9`, `-` and `_`. Files clashing with an ipset from the
+    /// cluster configuration or a generated guest ipset are ignored as well.
+    pub fn parse_ipset_files(
+        firewall_loader: &dyn FirewallConfigLoader,
+        cluster_config: &ClusterConfig,
+        guest_network_ipsets: &BTreeMap<String, Ipset>,
+    ) -> Result<BTreeMap<String, Ipset>, Error> {
+        let mut ipsets = BTreeMap::new();
+
+        for file_name in firewall_loader.ipset_file_list()? {
+            let (name, is_json) = match file_name.rsplit_once('.') {
+                Some((name, "list")) => (name.to_string(), false),
+                Some((name, "json")) => (name.to_string(), true),
+                _ => {
+                    log::debug!("ignoring ipset file {file_name}, unknown file extension");
+                    continue;
+                }
+            };
+
+            if !is_valid_ipset_name(&name) {
+                log::warn!("ipset file {file_name} has an invalid ipset name, ignoring it");
+                continue;
+            }
+
+            if cluster_config.ipsets().contains_key(&name)
+                || guest_network_ipsets.contains_key(&name)
+                || is_guest_network_ipset_name(&name)
+                || ipsets.contains_key(&name)
+            {
+                log::warn!("ipset dc/{name} is already defined, ignoring ipset file {file_name}");
+                continue;
+            }
+
+            let data = match firewall_loader.ipset_file(&file_name) {
+                Ok(Some(data)) => data,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::error!("unable to open ipset file {file_name}, ignoring it: {err:#}");
+                    continue;
+                }
+            };
+
+            let mut raw_data = Vec::new();
+
+            if let Err(err) = data
+                .take(MAX_IPSET_FILE_SIZE + 1)
+                .read_to_end(&mut raw_data)
+            {
+                log::error!("unable to read ipset file {file_name}, ignoring it: {err}");
+                continue;
+            }
+
+            if raw_data.len() as u64 > MAX_IPSET_FILE_SIZE {
+                log::error!(
+                    "ipset file {file_name} exceeds the maximum size of {MAX_IPSET_FILE_SIZE} bytes, ignoring it"
+                );
+                continue;
+            }
+
+            let raw_data = match String::from_utf8(raw_data) {
+                Ok(raw_data) => raw_data,
+                Err(err) => {
+                    log::error!("ipset file {file_name} is not valid UTF-8, ignoring it: {err}");
+                    continue;
+                }
+            };
+
+            let lines: Vec<(usize, String)> = if is_json {
+                match serde_json::from_str::<Vec<String>>(&raw_data) {
+                    Ok(entries) => entries.into_iter().enumerate().collect(),
+                    Err(err) => {
+                        log::error!("ipset file {file_name} is not a valid JSON array: {err}");
+                        continue;
+                    }
+                }
+            } else {
+                raw_data
+                    .lines()
+                    .map(|line| line.split_once('#').map_or(line, |(entry, _)| entry))
+                    .map(str::to_string)
+                    .enumerate()
+                    .collect()
+            };
+
+            let mut ipset = Ipset::new(IpsetName::new(IpsetScope::Datacenter, name.clone()));
+            let mut invalid_entries = 0;
+
+            for (idx, line) in lines {
+                let line = line.trim();
+
+                if line.is_empty() {
+                    continue;
+                }
+
+                let (nomatch, address) = match line.strip_prefix('!') {
+                    Some(address) => (true, address.trim()),
+                    None => (false, line),
+                };
+
+                let cidrs = match address.parse::<Cidr>() {
+                    Ok(cidr) => vec![cidr],
+                    Err(_) if is_hostname(address) => {
+                        match firewall_loader.resolve_hostname(address) {
+                            Ok(addresses) => addresses
+                                .into_iter()
+                                .map(|address| match address {
+                                    IpAddr::V4(address) => Cidr::from(Ipv4Cidr::from(address)),
+                                    IpAddr::V6(address) => Cidr::from(Ipv6Cidr::from(address)),
+                                })
+                                .collect(),
+                            Err(err) => {
+                                log::warn!("ipset file {file_name}, entry {}: {err:#}", idx + 1);
+                                invalid_entries += 1;
+                                continue;
+                            }
+                        }
+                    }
+                    Err(_) => {
+                        log::warn!(
+                            "ipset file {file_name}, entry {}: invalid CIDR '{line}'",
+                            idx + 1
+                        );
+                        invalid_entries += 1;
+                        continue;
+                    }
+                };
+
+                for cidr in cidrs {
+                    let mut entry = IpsetEntry::from(cidr);
+                    entry.nomatch = nomatch;
+                    ipset.push(entry);
+                }
+            }
+
+            if invalid_entries > 0 {
+                log::warn!("skipped {invalid_entries} invalid entries in ipset file {file_name}");
+            }
+
+            ipsets.insert(name, ipset);
+        }
+
+        Ok(ipsets)
+    }
+
     pub fn parse_nft(
         nft_loader: &dyn NftConfigLoader,
     ) -> Result<BTreeMap<String, ListChain>, Error> {
@@ -437,14 +884,60 @@ impl FirewallConfig {
         firewall_loader: &dyn FirewallConfigLoader,
         nft_loader: &dyn NftConfigLoader,
     ) -> Result<Self, Error> {
+        let cluster_config = Self::parse_cluster(firewall_loader)?;
+        let host_config = Self::parse_host(firewall_loader)?;
+        let guest_config = Self::parse_guests(firewall_loader)?;
+        let bridge_config = Self::parse_bridges(firewall_loader)?;
+
+        let group_rules = cluster_config
+            .groups()
+            .values()
+            .flat_map(|group| group.rules());
+        let bridge_rules = bridge_config.values().flat_map(|config| config.rules());
+
+        let mut cluster_table_references = BTreeSet::new();
+        collect_guest_network_ipset_references(
+            cluster_config
+                .rules()
+                .iter()
+                .chain(host_config.rules())
+                .chain(group_rules.clone())
+                .chain(bridge_rules.clone()),
+            &mut cluster_table_references,
+        );
+
+        let mut guest_table_references = BTreeSet::new();
+        collect_guest_network_ipset_references(
+            guest_config
+                .values()
+                .flat_map(|config| config.rules())
+                .chain(group_rules)
+                .chain(bridge_rules),
+            &mut guest_table_references,
+        );
+
+        let references = cluster_table_references
+            .union(&guest_table_references)
+            .cloned()
+            .collect();
+
+        let guest_network_ipsets =
+            Self::generate_guest_network_ipsets(firewall_loader, &cluster_config, &references)?;
+        let file_ipsets =
+            Self::parse_ipset_files(firewall_loader, &cluster_config, &guest_network_ipsets)?;
+
         Ok(Self {
-            cluster_config: Self::parse_cluster(firewall_loader)?,
-            host_config: Self::parse_host(firewall_loader)?,
-            guest_config: Self::parse_guests(firewall_loader)?,
-            bridge_config: Self::parse_bridges(firewall_loader)?,
+            cluster_config,
+            host_config,
+            guest_config,
+            bridge_config,
             sdn_config: Self::parse_sdn(firewall_loader)?,
             ipam_config: Self::parse_ipam(firewall_loader)?,
             nft_config: Self::parse_nft(nft_loader)?,
+            guest_network_ipsets,
+            cluster_table_references,
+            guest_table_references,
+            file_ipsets,
             interface_mapping: firewall_loader.interface_mapping()?,
         })
     }
@@ -477,6 +970,31 @@ impl FirewallConfig {
         self.ipam_config.as_ref()
     }
 
+    fn referenced_guest_network_ipsets(
+        &self,
+        references: &BTreeSet<String>,
+    ) -> BTreeMap<String, Ipset> {
+        self.guest_network_ipsets
+            .iter()
+            .filter(|(name, _)| references.contains(*name))
+            .map(|(name, ipset)| (name.clone(), ipset.clone()))
+            .collect()
+    }
+
+    /// Returns the generated guest ipsets referenced by the cluster, host, group or bridge rules.
+    pub fn cluster_table_guest_network_ipsets(&self) -> BTreeMap<String, Ipset> {
+        self.referenced_guest_network_ipsets(&self.cluster_table_references)
+    }
+
+    /// Returns the generated guest ipsets referenced by the guest, group or bridge rules.
+    pub fn guest_table_guest_network_ipsets(&self) -> BTreeMap<String, Ipset> {
+        self.referenced_guest_network_ipsets(&self.guest_table_references)
+    }
+
+    pub fn file_ipsets(&self) -> &BTreeMap<String, Ipset> {
+        &self.file_ipsets
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.cluster().is_enabled() && self.host().nftables()
     }
@@ -528,14 +1046,199 @@ impl FirewallConfig {
                     .sdn()?
                     .ipset(ipset_name.name())
                     .or_else(|| self.ipam()?.ipset(ipset_name.name())),
-                IpsetScope::Datacenter => self.cluster().ipset(ipset_name.name()),
+                IpsetScope::Datacenter => self
+                    .cluster()
+                    .ipset(ipset_name.name())
+                    .or_else(|| self.guest_network_ipsets.get(ipset_name.name()))
+                    .or_else(|| self.file_ipsets.get(ipset_name.name())),
                 IpsetScope::Guest => {
                     vmid.and_then(|vmid| self.guest_ipset(ipset_name.name(), vmid))
                 }
             },
             RuleIpsetName::Legacy(legacy_ipset_name) => vmid
                 .and_then(|vmid| self.guest_ipset(legacy_ipset_name.as_ref(), vmid))
-                .or_else(|| self.cluster().ipset(legacy_ipset_name.as_ref())),
+                .or_else(|| self.cluster().ipset(legacy_ipset_name.as_ref()))
+                .or_else(|| {
+                    self.guest_network_ipsets
+                        .get::<str>(legacy_ipset_name.as_ref())
+                })
+                .or_else(|| self.file_ipsets.get::<str>(legacy_ipset_name.as_ref())),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+    use std::io::BufRead;
+
+    use proxmox_sys::nodename;
+    use proxmox_ve_config::guest::GuestType;
+
+    use super::*;
+
+    /// Loads the configuration used by the integration tests.
+    pub(crate) struct FixtureLoader;
+
+    impl FirewallConfigLoader for FixtureLoader {
+        fn cluster(&self) -> Result<Option<Box<dyn BufRead>>, Error> {
+            Ok(Some(Box::new(
+                include_str!("../tests/input/cluster.fw").as_bytes(),
+            )))
         }
+
+        fn host(&self) -> Result<Option<Box<dyn BufRead>>, Error> {
+            Ok(Some(Box::new(
+                include_str!("../tests/input/host.fw").as_bytes(),
+            )))
+        }
+
+        fn guest_list(&self) -> Result<GuestMap, Error> {
+            let hostname = nodename().to_string();
+
+            let mut map = HashMap::new();
+            map.insert(101.into(), GuestEntry::new(hostname.clone(), GuestType::Vm));
+            map.insert(100.into(), GuestEntry::new(hostname, GuestType::Ct));
+
+            Ok(GuestMap::from(map))
+        }
+
+        fn guest_config(
+            &self,
+            vmid: &Vmid,
+            _guest: &GuestEntry,
+        ) -> Result<Option<Box<dyn BufRead>>, Error> {
+            if *vmid == Vmid::new(100) {
+                return Ok(Some(Box::new(
+                    include_str!("../tests/input/100.conf").as_bytes(),
+                )));
+            }
+
+            if *vmid == Vmid::new(101) {
+                return Ok(Some(Box::new(
+                    include_str!("../tests/input/101.conf").as_bytes(),
+                )));
+            }
+
+            Ok(None)
+        }
+
+        fn guest_firewall_config(&self, vmid: &Vmid) -> Result<Option<Box<dyn BufRead>>, Error> {
+            if *vmid == Vmid::new(100) {
+                return Ok(Some(Box::new(
+                    include_str!("../tests/input/100.fw").as_bytes(),
+                )));
+            }
+
+            if *vmid == Vmid::new(101) {
+                return Ok(Some(Box::new(
+                    include_str!("../tests/input/101.fw").as_bytes(),
+                )));
+            }
+
+            Ok(None)
+        }
+
+        fn sdn_running_config(&self) -> Result<Option<Box<dyn BufRead>>, Error> {
+            Ok(Some(Box::new(
+                include_str!("../tests/input/.running-config.json").as_bytes(),
+            )))
+        }
+
+        fn ipam(&self) -> Result<Option<Box<dyn BufRead>>, Error> {
+            Ok(Some(Box::new(
+                include_str!("../tests/input/ipam.db").as_bytes(),
+            )))
+        }
+
+        fn bridge_list(&self) -> Result<Vec<BridgeName>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn bridge_firewall_config(
+            &self,
+            _bridge_name: &BridgeName,
+        ) -> Result<Option<Box<dyn BufRead>>, Error> {
+            Ok(None)
+        }
+
+        fn interface_mapping(&self) -> Result<AltnameMapping, Error> {
+            Ok(AltnameMapping::from_iter(vec![]))
+        }
+
+        fn ipset_file_list(&self) -> Result<Vec<String>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn ipset_file(&self, _file_name: &str) -> Result<Option<Box<dyn BufRead>>, Error> {
+            Ok(None)
+        }
+
+        fn resolve_hostname(&self, hostname: &str) -> Result<Vec<IpAddr>, Error> {
+            bail!("unable to resolve hostname {hostname}")
+        }
+    }
+
+    impl NftConfigLoader for FixtureLoader {
+        fn chains(&self) -> Result<Option<CommandOutput>, Error> {
+            Ok(Some(serde_json::from_str(include_str!(
+                "../tests/input/chains.json"
+            ))?))
+        }
+    }
+
+    #[test]
+    fn test_parse_guest_tags() {
+        // PVE separates tags by `;`, `,` or whitespace, see `PVE::Tools::split_list`
+        let raw_config = "cores: 1\ntags: web;db.backend, prod \tTest_1\n\n[snapshot]\ntags: old\n";
+
+        assert_eq!(
+            parse_guest_tags(raw_config),
+            vec!["web", "db.backend", "prod", "Test_1"],
+        );
+
+        assert!(parse_guest_tags("cores: 1\n\n[snapshot]\ntags: old\n").is_empty());
+
+        let valid_tags: Vec<String> = parse_guest_tags(raw_config)
+            .into_iter()
+            .filter(|tag| is_valid_ipset_name(tag))
+            .collect();
+
+        assert_eq!(valid_tags, vec!["web", "prod", "Test_1"]);
+    }
+
+    #[test]
+    fn test_parse_cloudinit_addresses() {
+        let raw_config = "\
+ipconfig0: ip=192.0.2.40/24,gw=192.0.2.1,ip6=fd80::40/64
+ipconfig1: ip=dhcp,ip6=auto
+ipconfig2: ip6=dhcp
+net0: virtio=BC:24:11:4D:B0:FC,bridge=vmbr0
+
+[snapshot]
+ipconfig0: ip=198.51.100.1/24
+";
+
+        assert_eq!(
+            parse_cloudinit_addresses(raw_config),
+            vec![
+                Cidr::from(Ipv4Cidr::from(Ipv4Addr::new(192, 0, 2, 40))),
+                Cidr::from(Ipv6Cidr::from(Ipv6Addr::new(
+                    0xfd80, 0, 0, 0, 0, 0, 0, 0x40
+                ))),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_guest_network_ipset_names() {
+        assert!(is_guest_network_ipset_name("guests-100"));
+        assert!(is_guest_network_ipset_name("guests-tag-web"));
+        assert!(is_guest_network_ipset_name("guests-tag-Test_1"));
+
+        assert!(!is_guest_network_ipset_name("guests-web"));
+        assert!(!is_guest_network_ipset_name("guests-tag-"));
+        assert!(!is_guest_network_ipset_name("guests-tag-db.backend"));
+        assert!(!is_guest_network_ipset_name("network1"));
     }
 }
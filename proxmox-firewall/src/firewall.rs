@@ -48,19 +48,35 @@ static NF_CONNTRACK_TCP_TIMEOUT_SYN_RECV: &str =
     "/proc/sys/net/netfilter/nf_conntrack_tcp_timeout_syn_recv";
 static LOG_CONNTRACK_FILE: &str = "/var/lib/pve-firewall/log_nf_conntrack";
 
+/// The NFLOG group the log statements of the generated rules send packets to.
+pub const NFLOG_GROUP: u16 = 0;
+
 pub struct Firewall {
     config: FirewallConfig,
+    compress_rules: bool,
 }
 
 impl From<FirewallConfig> for Firewall {
     fn from(config: FirewallConfig) -> Self {
-        Self { config }
+        Self::new(config)
     }
 }
 
 impl Firewall {
     pub fn new(config: FirewallConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            compress_rules: true,
+        }
+    }
+
+    /// Sets whether adjacent rules get merged into set and verdict map lookups.
+    ///
+    /// Disabling this generates one nftables rule per config rule, which is easier to match up
+    /// with the configuration when debugging.
+    pub fn with_rule_compression(mut self, compress_rules: bool) -> Self {
+        self.compress_rules = compress_rules;
+        self
     }
 
     pub fn is_enabled(&self) -> bool {
@@ -111,6 +127,10 @@ impl Firewall {
         ChainPart::new(Self::host_table(), "ct-in".to_string())
     }
 
+    fn guest_conntrack_chain() -> ChainPart {
+        ChainPart::new(Self::guest_table(), "ct-helper".to_string())
+    }
+
     fn host_option_chain(dir: Direction) -> ChainPart {
         ChainPart::new(Self::host_table(), format!("option-{dir}"))
     }
@@ -158,6 +178,7 @@ impl Firewall {
             Flush::map(Self::bridge_vmap(Self::guest_table())),
             Flush::map(Self::bridge_vmap(Self::host_table())),
             Flush::chain(Self::host_conntrack_chain()),
+            Flush::chain(Self::guest_conntrack_chain()),
             Flush::chain(Self::synflood_limit_chain()),
             Flush::chain(Self::log_invalid_tcp_chain()),
             Flush::chain(Self::log_smurfs_chain()),
@@ -267,6 +288,20 @@ impl Firewall {
                 None,
             )?;
 
+            self.create_ipsets(
+                &mut commands,
+                &self.config.cluster_table_guest_network_ipsets(),
+                &cluster_host_table,
+                None,
+            )?;
+
+            self.create_ipsets(
+                &mut commands,
+                self.config.file_ipsets(),
+                &cluster_host_table,
+                None,
+            )?;
+
             for (name, group) in self.config.cluster().groups() {
                 self.create_group_chain(
                     &mut commands,
@@ -334,6 +369,15 @@ impl Firewall {
                 None,
             )?;
 
+            self.create_ipsets(
+                &mut commands,
+                &self.config.guest_table_guest_network_ipsets(),
+                &guest_table,
+                None,
+            )?;
+
+            self.create_ipsets(&mut commands, self.config.file_ipsets(), &guest_table, None)?;
+
             for (name, group) in self.config.cluster().groups() {
                 self.create_group_chain(&mut commands, &guest_table, group, name, Direction::In)?;
                 self.create_group_chain(&mut commands, &guest_table, group, name, Direction::Out)?;
@@ -345,6 +389,8 @@ impl Firewall {
                     Direction::Forward,
                 )?;
             }
+
+            self.setup_guest_ct_helper(&mut commands)?;
         } else {
             commands.push(Delete::table(TableName::from(Self::guest_table())));
         }
@@ -403,13 +449,12 @@ impl Firewall {
             chain: chain.clone(),
             direction: Direction::Forward,
             firewall_config: &self.config,
+            compress_rules: self.compress_rules,
             vmid: None,
         };
 
-        for config_rule in config.rules() {
-            for rule in NftRule::from_config_rule(config_rule, &env)? {
-                commands.push(Add::rule(rule.into_add_rule(chain.clone())));
-            }
+        for rule in NftRule::from_config_rules(config.rules(), &env)? {
+            commands.push(Add::rule(rule.into_add_rule(chain.clone())));
         }
 
         let default_policy = config.policy_forward();
@@ -722,24 +767,43 @@ impl Firewall {
     }
 
     fn setup_ct_helper(&self, commands: &mut Commands) -> Result<(), Error> {
-        let chain_in = Self::host_conntrack_chain();
+        self.create_ct_helpers(commands, Self::host_conntrack_chain(), true)
+    }
 
+    /// Assigns the conntrack helpers enabled on the host to guest connections as well.
+    ///
+    /// In contrast to [`Self::setup_ct_helper`], this only assigns the helpers and does not
+    /// accept any traffic, so the guest rules still decide whether a connection is allowed.
+    /// Related connections are then accepted by the conntrack rules in `pre-vm-in` / `pre-vm-out`.
+    fn setup_guest_ct_helper(&self, commands: &mut Commands) -> Result<(), Error> {
+        self.create_ct_helpers(commands, Self::guest_conntrack_chain(), false)
+    }
+
+    /// Creates the conntrack helpers enabled on the host in the table of `chain` and adds the
+    /// rules assigning them to `chain`, see [`NftRule::from_ct_helper`].
+    fn create_ct_helpers(
+        &self,
+        commands: &mut Commands,
+        chain: ChainPart,
+        accept: bool,
+    ) -> Result<(), Error> {
         if let Some(helpers) = self.config.host().conntrack_helpers() {
             let object_env = NftObjectEnv {
-                table: chain_in.table(),
+                table: chain.table(),
                 firewall_config: &self.config,
                 vmid: None,
             };
 
             let rule_env = NftRuleEnv {
-                chain: chain_in.clone(),
+                chain: chain.clone(),
                 direction: Direction::In,
                 firewall_config: &self.config,
+                compress_rules: self.compress_rules,
                 vmid: None,
             };
 
             for helper in helpers {
-                log::debug!("adding conntrack helper: {helper:?}");
+                log::debug!("adding conntrack helper to {}: {helper:?}", chain.name());
 
                 let helper_macro = get_cthelper(&helper.to_string());
 
@@ -747,8 +811,8 @@ impl Firewall {
                     commands.append(&mut helper_macro.to_nft_objects(&object_env)?);
 
                     // todo: use vmap
-                    for rule in NftRule::from_ct_helper(helper_macro, &rule_env)? {
-                        commands.push(Add::rule(rule.into_add_rule(chain_in.clone())));
+                    for rule in NftRule::from_ct_helper(helper_macro, &rule_env, accept)? {
+                        commands.push(Add::rule(rule.into_add_rule(chain.clone())));
                     }
                 } else {
                     log::warn!("provided invalid helper macro name: {:?}", helper);
@@ -772,6 +836,7 @@ impl Firewall {
                 chain: chain.clone(),
                 direction,
                 firewall_config: &self.config,
+                compress_rules: self.compress_rules,
                 vmid: Some(vmid),
             };
 
@@ -871,6 +936,7 @@ impl Firewall {
             chain: chain.clone(),
             direction,
             firewall_config: &self.config,
+            compress_rules: self.compress_rules,
             vmid: None,
         };
 
@@ -878,10 +944,8 @@ impl Firewall {
 
         commands.reserve(rules.len());
 
-        for config_rule in rules {
-            for rule in NftRule::from_config_rule(config_rule, &env)? {
-                commands.push(Add::rule(rule.into_add_rule(chain.clone())));
-            }
+        for rule in NftRule::from_config_rules(rules, &env)? {
+            commands.push(Add::rule(rule.into_add_rule(chain.clone())));
         }
 
         let default_policy = self.config.cluster().default_policy(direction);
@@ -915,16 +979,15 @@ impl Firewall {
             chain: chain.clone(),
             direction,
             firewall_config: &self.config,
+            compress_rules: self.compress_rules,
             vmid: None,
         };
 
         let rules = self.config.host().rules();
         commands.reserve(rules.len());
 
-        for config_rule in rules {
-            for rule in NftRule::from_config_rule(config_rule, &env)? {
-                commands.push(Add::rule(rule.into_add_rule(chain.clone())));
-            }
+        for rule in NftRule::from_config_rules(rules, &env)? {
+            commands.push(Add::rule(rule.into_add_rule(chain.clone())));
         }
 
         Ok(())
@@ -970,6 +1033,7 @@ impl Firewall {
             chain: chain.clone(),
             direction,
             firewall_config: &self.config,
+            compress_rules: self.compress_rules,
             vmid: Some(vmid),
         };
 
@@ -983,10 +1047,8 @@ impl Firewall {
             Mangle::ct_mark(vmid),
         )));
 
-        for config_rule in config.rules() {
-            for rule in NftRule::from_config_rule(config_rule, &env)? {
-                commands.push(Add::rule(rule.into_add_rule(chain.clone())))
-            }
+        for rule in NftRule::from_config_rules(config.rules(), &env)? {
+            commands.push(Add::rule(rule.into_add_rule(chain.clone())))
         }
 
         let network_devices = config.network_config().network_devices();
@@ -1048,6 +1110,7 @@ impl Firewall {
             chain: chain.clone(),
             direction,
             firewall_config: &self.config,
+            compress_rules: self.compress_rules,
             vmid: None,
         };
 
@@ -1056,10 +1119,8 @@ impl Firewall {
             Flush::chain(chain.clone()),
         ]);
 
-        for rule in group.rules() {
-            for firewall_rule in NftRule::from_config_rule(rule, &env)? {
-                commands.push(Add::rule(firewall_rule.into_add_rule(chain.clone())))
-            }
+        for rule in NftRule::from_config_rules(group.rules(), &env)? {
+            commands.push(Add::rule(rule.into_add_rule(chain.clone())))
         }
 
         Ok(())
@@ -1082,7 +1143,7 @@ impl Firewall {
 
             let log_statement = Log::new_nflog(
                 Log::generate_prefix(vmid, log_level, chain.name(), verdict),
-                0,
+                NFLOG_GROUP.into(),
             );
 
             log_rule.push(Statement::from(log_statement));
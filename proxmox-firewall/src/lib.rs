@@ -1,4 +1,8 @@
 pub mod config;
 pub mod firewall;
+pub mod logger;
+pub mod nflog;
 pub mod object;
+pub(crate) mod optimize;
+pub mod resolver;
 pub mod rule;
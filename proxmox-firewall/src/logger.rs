@@ -0,0 +1,631 @@
+//! Conversion of packets logged via NFLOG into firewall log entries.
+//!
+//! The classic format matches the one written by pvefw-logger, so existing consumers of
+//! `/var/log/pve-firewall.log` (e.g. the log viewer in the web UI) keep working. Additionally the
+//! entries can be written as JSON lines to a separate file.
+
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Error};
+use serde::Serialize;
+
+use proxmox_nftables::statement::LogPrefix;
+
+use crate::nflog::{NflogPacket, interface_name};
+
+pub const FIREWALL_LOG_FILE: &str = "/var/log/pve-firewall.log";
+pub const FIREWALL_JSON_LOG_FILE: &str = "/var/log/pve-firewall.json";
+
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_ARP: u16 = 0x0806;
+const ETH_P_IPV6: u16 = 0x86dd;
+
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+
+/// Log level used for packets whose prefix was not generated by us.
+const DEFAULT_LOG_LEVEL: u8 = 6;
+
+/// A single entry of the firewall log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LogEntry {
+    pub vmid: u32,
+    pub level: u8,
+    pub chain: String,
+    /// seconds since the epoch
+    pub time: i64,
+    pub message: String,
+    #[serde(rename = "in", skip_serializing_if = "Option::is_none")]
+    pub indev: Option<String>,
+    #[serde(rename = "out", skip_serializing_if = "Option::is_none")]
+    pub outdev: Option<String>,
+    #[serde(rename = "physin", skip_serializing_if = "Option::is_none")]
+    pub physindev: Option<String>,
+    #[serde(rename = "physout", skip_serializing_if = "Option::is_none")]
+    pub physoutdev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkHeader>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<TransportHeader>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mark: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase", tag = "protocol")]
+pub enum NetworkHeader {
+    Ipv4 {
+        src: Ipv4Addr,
+        dst: Ipv4Addr,
+        len: u16,
+        tos: u8,
+        ttl: u8,
+        id: u16,
+        /// the CE, DF and MF flags that are set
+        flags: Vec<&'static str>,
+        fragment_offset: u16,
+    },
+    Ipv6 {
+        src: Ipv6Addr,
+        dst: Ipv6Addr,
+        len: u16,
+        traffic_class: u8,
+        hop_limit: u8,
+        flow_label: u32,
+    },
+    Arp,
+    Unknown {
+        ethertype: u16,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase", tag = "protocol")]
+pub enum TransportHeader {
+    Tcp {
+        sport: u16,
+        dport: u16,
+        seq: u32,
+        ack: u32,
+        window: u16,
+        flags: Vec<&'static str>,
+        urgent: u16,
+    },
+    Udp {
+        sport: u16,
+        dport: u16,
+        len: u16,
+    },
+    Icmp {
+        #[serde(rename = "type")]
+        ty: u8,
+        code: u8,
+    },
+    Icmpv6 {
+        #[serde(rename = "type")]
+        ty: u8,
+        code: u8,
+    },
+    Unknown {
+        number: u8,
+    },
+}
+
+impl LogEntry {
+    pub fn from_packet(packet: &NflogPacket) -> Self {
+        let prefix = packet.prefix.as_deref().unwrap_or_default();
+
+        let (vmid, level, chain, message) = match prefix.parse::<LogPrefix>() {
+            Ok(prefix) => (
+                prefix.vmid.raw_value(),
+                prefix.log_level.nflog_level(),
+                prefix.chain_name,
+                prefix.verdict.to_string(),
+            ),
+            Err(_) => (
+                0,
+                DEFAULT_LOG_LEVEL,
+                "unknown".to_string(),
+                prefix.trim().trim_end_matches(':').to_string(),
+            ),
+        };
+
+        let time = packet
+            .timestamp
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+
+        let mac = (!packet.hwheader.is_empty()).then(|| {
+            packet
+                .hwheader
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<String>>()
+                .join(":")
+        });
+
+        let interface = |index: Option<u32>| {
+            index
+                .filter(|index| *index > 0)
+                .map(|index| interface_name(index).unwrap_or_else(|| index.to_string()))
+        };
+
+        let ethertype = match packet.family as i32 {
+            libc::AF_INET => ETH_P_IP,
+            libc::AF_INET6 => ETH_P_IPV6,
+            _ => packet.hw_protocol,
+        };
+
+        let (network, transport) = parse_headers(ethertype, &packet.payload);
+
+        Self {
+            vmid,
+            level,
+            chain,
+            time,
+            message,
+            indev: interface(packet.indev),
+            outdev: interface(packet.outdev),
+            physindev: interface(packet.physindev),
+            physoutdev: interface(packet.physoutdev),
+            mac,
+            network,
+            transport,
+            mark: (packet.mark != 0).then_some(packet.mark),
+        }
+    }
+}
+
+fn parse_headers(
+    ethertype: u16,
+    payload: &[u8],
+) -> (Option<NetworkHeader>, Option<TransportHeader>) {
+    match ethertype {
+        ETH_P_IP => match parse_ipv4(payload) {
+            Some((network, protocol, data)) => {
+                (Some(network), parse_transport(protocol, false, data))
+            }
+            None => (None, None),
+        },
+        ETH_P_IPV6 => match parse_ipv6(payload) {
+            Some((network, protocol, data)) => {
+                (Some(network), parse_transport(protocol, true, data))
+            }
+            None => (None, None),
+        },
+        ETH_P_ARP => (Some(NetworkHeader::Arp), None),
+        ethertype => (Some(NetworkHeader::Unknown { ethertype }), None),
+    }
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn parse_ipv4(data: &[u8]) -> Option<(NetworkHeader, u8, &[u8])> {
+    if data.len() < 20 || data[0] >> 4 != 4 {
+        return None;
+    }
+
+    let header_len = usize::from(data[0] & 0x0f) * 4;
+    let fragment = be16(data, 6)?;
+
+    let mut flags = Vec::new();
+
+    for (bit, name) in [(0x8000, "CE"), (0x4000, "DF"), (0x2000, "MF")] {
+        if fragment & bit != 0 {
+            flags.push(name);
+        }
+    }
+
+    let fragment_offset = fragment & 0x1fff;
+
+    let header = NetworkHeader::Ipv4 {
+        src: Ipv4Addr::from(be32(data, 12)?),
+        dst: Ipv4Addr::from(be32(data, 16)?),
+        len: be16(data, 2)?,
+        tos: data[1],
+        ttl: data[8],
+        id: be16(data, 4)?,
+        flags,
+        fragment_offset,
+    };
+
+    // only the first fragment contains the transport header
+    let transport = match fragment_offset {
+        0 => data.get(header_len..).unwrap_or_default(),
+        _ => &[],
+    };
+
+    Some((header, data[9], transport))
+}
+
+fn parse_ipv6(data: &[u8]) -> Option<(NetworkHeader, u8, &[u8])> {
+    if data.len() < 40 || data[0] >> 4 != 6 {
+        return None;
+    }
+
+    let first_word = be32(data, 0)?;
+
+    let header = NetworkHeader::Ipv6 {
+        src: Ipv6Addr::from(<[u8; 16]>::try_from(&data[8..24]).ok()?),
+        dst: Ipv6Addr::from(<[u8; 16]>::try_from(&data[24..40]).ok()?),
+        len: be16(data, 4)?,
+        traffic_class: ((first_word >> 20) & 0xff) as u8,
+        hop_limit: data[7],
+        flow_label: first_word & 0x000f_ffff,
+    };
+
+    Some((header, data[6], &data[40..]))
+}
+
+fn parse_transport(protocol: u8, ipv6: bool, data: &[u8]) -> Option<TransportHeader> {
+    let header = match protocol {
+        IPPROTO_TCP if data.len() >= 20 => {
+            let mut flags = Vec::new();
+
+            for (bit, name) in [
+                (0x80, "CWR"),
+                (0x40, "ECE"),
+                (0x20, "URG"),
+                (0x10, "ACK"),
+                (0x08, "PSH"),
+                (0x04, "RST"),
+                (0x02, "SYN"),
+                (0x01, "FIN"),
+            ] {
+                if data[13] & bit != 0 {
+                    flags.push(name);
+                }
+            }
+
+            TransportHeader::Tcp {
+                sport: be16(data, 0)?,
+                dport: be16(data, 2)?,
+                seq: be32(data, 4)?,
+                ack: be32(data, 8)?,
+                window: be16(data, 14)?,
+                flags,
+                urgent: be16(data, 18)?,
+            }
+        }
+        IPPROTO_UDP if data.len() >= 8 => TransportHeader::Udp {
+            sport: be16(data, 0)?,
+            dport: be16(data, 2)?,
+            len: be16(data, 4)?,
+        },
+        IPPROTO_ICMP if !ipv6 && data.len() >= 2 => TransportHeader::Icmp {
+            ty: data[0],
+            code: data[1],
+        },
+        IPPROTO_ICMPV6 if ipv6 && data.len() >= 2 => TransportHeader::Icmpv6 {
+            ty: data[0],
+            code: data[1],
+        },
+        number => TransportHeader::Unknown { number },
+    };
+
+    Some(header)
+}
+
+/// Formats a timestamp in the local timezone, as in `14/Aug/2024:10:12:31 +0200`.
+fn format_time(time: i64) -> String {
+    let time = time as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return time.to_string();
+    }
+
+    let mut buffer = [0u8; 64];
+
+    let len = unsafe {
+        libc::strftime(
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+            c"%d/%b/%Y:%H:%M:%S %z".as_ptr(),
+            &tm,
+        )
+    };
+
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+impl fmt::Display for NetworkHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkHeader::Ipv4 {
+                src,
+                dst,
+                len,
+                tos,
+                ttl,
+                id,
+                flags,
+                fragment_offset,
+            } => {
+                write!(
+                    f,
+                    "SRC={src} DST={dst} LEN={len} TOS=0x{:02X} PREC=0x{:02X} TTL={ttl} ID={id} ",
+                    tos & 0x1e,
+                    tos & 0xe0,
+                )?;
+
+                for flag in flags {
+                    write!(f, "{flag} ")?;
+                }
+
+                if *fragment_offset != 0 {
+                    write!(f, "FRAG={fragment_offset} ")?;
+                }
+
+                Ok(())
+            }
+            NetworkHeader::Ipv6 {
+                src,
+                dst,
+                len,
+                traffic_class,
+                hop_limit,
+                flow_label,
+            } => write!(
+                f,
+                "SRC={src} DST={dst} LEN={len} TC={traffic_class} HOPLIMIT={hop_limit} FLOWLBL={flow_label} "
+            ),
+            NetworkHeader::Arp => write!(f, "PROTO=ARP "),
+            NetworkHeader::Unknown { ethertype } => write!(f, "proto 0x{ethertype:04x} unknown "),
+        }
+    }
+}
+
+impl fmt::Display for TransportHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportHeader::Tcp {
+                sport,
+                dport,
+                seq,
+                ack,
+                window,
+                flags,
+                urgent,
+            } => {
+                write!(
+                    f,
+                    "PROTO=TCP SPT={sport} DPT={dport} SEQ={seq} ACK={ack} WINDOW={window} "
+                )?;
+
+                for flag in flags {
+                    write!(f, "{flag} ")?;
+                }
+
+                write!(f, "URGP={urgent} ")
+            }
+            TransportHeader::Udp { sport, dport, len } => {
+                write!(f, "PROTO=UDP SPT={sport} DPT={dport} LEN={len} ")
+            }
+            TransportHeader::Icmp { ty, code } => write!(f, "PROTO=ICMP TYPE={ty} CODE={code} "),
+            TransportHeader::Icmpv6 { ty, code } => {
+                write!(f, "PROTO=ICMPv6 TYPE={ty} CODE={code} ")
+            }
+            TransportHeader::Unknown { number } => write!(f, "PROTO={number} "),
+        }
+    }
+}
+
+impl LogEntry {
+    /// Formats the entry in the format of pvefw-logger, with the given formatted time.
+    fn format_classic(&self, time: &str) -> String {
+        let mut line = format!(
+            "{} {} {} {time} {}: ",
+            self.vmid, self.level, self.chain, self.message
+        );
+
+        let interfaces = [
+            ("IN", &self.indev),
+            ("OUT", &self.outdev),
+            ("PHYSIN", &self.physindev),
+            ("PHYSOUT", &self.physoutdev),
+        ];
+
+        for (key, name) in interfaces {
+            if let Some(name) = name {
+                let _ = write!(line, "{key}={name} ");
+            }
+        }
+
+        if let Some(mac) = &self.mac {
+            let _ = write!(line, "MAC={mac} ");
+        }
+
+        if let Some(network) = &self.network {
+            let _ = write!(line, "{network}");
+        }
+
+        if let Some(transport) = &self.transport {
+            let _ = write!(line, "{transport}");
+        }
+
+        if let Some(mark) = self.mark {
+            let _ = write!(line, "mark={mark} ");
+        }
+
+        line.truncate(line.trim_end().len());
+        line
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format_classic(&format_time(self.time)))
+    }
+}
+
+/// Output format of the firewall log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// the format of pvefw-logger
+    Classic,
+    /// one JSON object per line
+    Json,
+}
+
+/// Writes log entries to a file, reopening it if it got rotated.
+pub struct LogWriter {
+    path: PathBuf,
+    format: LogFormat,
+    file: File,
+    inode: u64,
+}
+
+impl LogWriter {
+    pub fn new(path: impl Into<PathBuf>, format: LogFormat) -> Result<Self, Error> {
+        let path = path.into();
+        let (file, inode) = Self::open(&path)?;
+
+        Ok(Self {
+            path,
+            format,
+            file,
+            inode,
+        })
+    }
+
+    fn open(path: &Path) -> Result<(File, u64), Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open log file {path:?}"))?;
+
+        let inode = file.metadata()?.ino();
+
+        Ok((file, inode))
+    }
+
+    /// Reopens the log file if it was moved or deleted, e.g. by logrotate.
+    fn reopen_if_rotated(&mut self) -> Result<(), Error> {
+        let rotated = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.ino() != self.inode,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => true,
+            Err(error) => return Err(error.into()),
+        };
+
+        if rotated {
+            (self.file, self.inode) = Self::open(&self.path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_entries(&mut self, entries: &[LogEntry]) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        self.reopen_if_rotated()?;
+
+        let mut output = String::new();
+
+        for entry in entries {
+            match self.format {
+                LogFormat::Classic => output.push_str(&entry.to_string()),
+                LogFormat::Json => output.push_str(&serde_json::to_string(entry)?),
+            }
+
+            output.push('\n');
+        }
+
+        self.file
+            .write_all(output.as_bytes())
+            .with_context(|| format!("cannot write to log file {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp_syn_packet() -> NflogPacket {
+        let mut payload = vec![
+            0x45, 0x00, 0x00, 0x3c, 0x1c, 0x46, 0x40, 0x00, 0x40, 0x06, 0x00, 0x00, 10, 0, 0, 1,
+            10, 0, 0, 2,
+        ];
+
+        payload.extend_from_slice(&[
+            0xc3, 0x50, 0x00, 0x16, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x50, 0x02,
+            0xfa, 0xf0, 0x00, 0x00, 0x00, 0x00,
+        ]);
+
+        NflogPacket {
+            family: libc::AF_INET as u8,
+            hw_protocol: ETH_P_IP,
+            prefix: Some(":100:6:guest-100-in: ACCEPT: ".to_string()),
+            timestamp: Some(UNIX_EPOCH),
+            hwheader: vec![0xbc, 0x24, 0x11, 0x00, 0x00, 0x01],
+            payload,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_classic_format() {
+        let entry = LogEntry::from_packet(&tcp_syn_packet());
+
+        assert_eq!(
+            entry.format_classic("01/Jan/1970:00:00:00 +0000"),
+            "100 6 guest-100-in 01/Jan/1970:00:00:00 +0000 ACCEPT: MAC=bc:24:11:00:00:01 \
+             SRC=10.0.0.1 DST=10.0.0.2 LEN=60 TOS=0x00 PREC=0x00 TTL=64 ID=7238 DF \
+             PROTO=TCP SPT=50000 DPT=22 SEQ=1000 ACK=0 WINDOW=64240 SYN URGP=0"
+        );
+    }
+
+    #[test]
+    fn test_json_format() {
+        let entry = LogEntry::from_packet(&tcp_syn_packet());
+        let json = serde_json::to_value(&entry).expect("can serialize log entry");
+
+        assert_eq!(json["vmid"], 100);
+        assert_eq!(json["chain"], "guest-100-in");
+        assert_eq!(json["network"]["protocol"], "ipv4");
+        assert_eq!(json["network"]["src"], "10.0.0.1");
+        assert_eq!(json["transport"]["protocol"], "tcp");
+        assert_eq!(json["transport"]["dport"], 22);
+        assert_eq!(json["transport"]["flags"][0], "SYN");
+    }
+
+    #[test]
+    fn test_foreign_prefix() {
+        let mut packet = tcp_syn_packet();
+        packet.prefix = Some("custom: ".to_string());
+        packet.payload.truncate(10);
+
+        let entry = LogEntry::from_packet(&packet);
+
+        assert_eq!(entry.vmid, 0);
+        assert_eq!(entry.chain, "unknown");
+        assert_eq!(entry.message, "custom");
+        assert_eq!(entry.network, None);
+    }
+}
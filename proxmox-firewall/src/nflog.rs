@@ -0,0 +1,589 @@
+//! Minimal NFLOG consumer on top of a netfilter netlink socket.
+//!
+//! Only the parts of the nfnetlink_log protocol required for receiving the packets logged by the
+//! `log` statements in our rules are implemented: binding to a group, requesting packet copies
+//! and parsing the attributes of the received packets.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Error, bail, format_err};
+
+use proxmox_log as log;
+
+const NFNL_SUBSYS_ULOG: u16 = 4;
+const NFULNL_MSG_PACKET: u16 = 0;
+const NFULNL_MSG_CONFIG: u16 = 1;
+
+const NFULA_CFG_CMD: u16 = 1;
+const NFULA_CFG_MODE: u16 = 2;
+
+const NFULNL_CFG_CMD_BIND: u8 = 1;
+const NFULNL_COPY_PACKET: u8 = 2;
+
+const NFULA_PACKET_HDR: u16 = 1;
+const NFULA_MARK: u16 = 2;
+const NFULA_TIMESTAMP: u16 = 3;
+const NFULA_IFINDEX_INDEV: u16 = 4;
+const NFULA_IFINDEX_OUTDEV: u16 = 5;
+const NFULA_IFINDEX_PHYSINDEV: u16 = 6;
+const NFULA_IFINDEX_PHYSOUTDEV: u16 = 7;
+const NFULA_PAYLOAD: u16 = 9;
+const NFULA_PREFIX: u16 = 10;
+const NFULA_HWHEADER: u16 = 16;
+
+const NLMSG_HDRLEN: usize = 16;
+const NFGENMSG_LEN: usize = 4;
+const NLA_HDRLEN: usize = 4;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const NLMSG_ERROR: u16 = 2;
+
+const NLM_F_REQUEST: u16 = 1;
+const NLM_F_ACK: u16 = 4;
+
+const NFNETLINK_V0: u8 = 0;
+
+/// Size of the socket receive buffer, large enough to not drop packets on short bursts.
+const RECEIVE_BUFFER_SIZE: libc::c_int = 4 * 1024 * 1024;
+
+/// How long [`NflogSocket::receive`] waits for packets before returning.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to wait for the acknowledgement of a config message.
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[inline]
+const fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// A packet as received from the kernel via NFLOG.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NflogPacket {
+    /// address family of the hook the packet was logged in
+    pub family: u8,
+    /// link layer protocol (ethertype) of the packet
+    pub hw_protocol: u16,
+    pub prefix: Option<String>,
+    pub mark: u32,
+    pub timestamp: Option<SystemTime>,
+    pub indev: Option<u32>,
+    pub outdev: Option<u32>,
+    pub physindev: Option<u32>,
+    pub physoutdev: Option<u32>,
+    pub hwheader: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl NflogPacket {
+    /// Parses the payload of a NFULNL_MSG_PACKET message, starting with the nfgenmsg header.
+    fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < NFGENMSG_LEN {
+            bail!("nflog packet message too short");
+        }
+
+        let mut packet = NflogPacket {
+            family: data[0],
+            ..Default::default()
+        };
+
+        for (ty, value) in Attributes::new(&data[NFGENMSG_LEN..]) {
+            match ty {
+                NFULA_PACKET_HDR => packet.hw_protocol = read_u16(value)?,
+                NFULA_MARK => packet.mark = read_u32(value)?,
+                NFULA_TIMESTAMP => {
+                    let sec = read_u64(value)?;
+                    let usec = read_u64(value.get(8..).unwrap_or_default())?;
+
+                    packet.timestamp =
+                        Some(UNIX_EPOCH + Duration::from_secs(sec) + Duration::from_micros(usec));
+                }
+                NFULA_IFINDEX_INDEV => packet.indev = Some(read_u32(value)?),
+                NFULA_IFINDEX_OUTDEV => packet.outdev = Some(read_u32(value)?),
+                NFULA_IFINDEX_PHYSINDEV => packet.physindev = Some(read_u32(value)?),
+                NFULA_IFINDEX_PHYSOUTDEV => packet.physoutdev = Some(read_u32(value)?),
+                NFULA_HWHEADER => packet.hwheader = value.to_vec(),
+                NFULA_PAYLOAD => packet.payload = value.to_vec(),
+                NFULA_PREFIX => {
+                    let prefix = value.split(|byte| *byte == 0).next().unwrap_or_default();
+                    packet.prefix = Some(String::from_utf8_lossy(prefix).into_owned());
+                }
+                _ => (),
+            }
+        }
+
+        Ok(packet)
+    }
+}
+
+fn read_u16(data: &[u8]) -> Result<u16, Error> {
+    data.get(..2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| format_err!("nflog attribute too short"))
+}
+
+fn read_u32(data: &[u8]) -> Result<u32, Error> {
+    data.get(..4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| format_err!("nflog attribute too short"))
+}
+
+fn read_u64(data: &[u8]) -> Result<u64, Error> {
+    data.get(..8)
+        .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| format_err!("nflog attribute too short"))
+}
+
+/// Iterator over the netlink attributes contained in a buffer.
+struct Attributes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Attributes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < NLA_HDRLEN {
+            return None;
+        }
+
+        let len = u16::from_ne_bytes([self.data[0], self.data[1]]) as usize;
+        let ty = u16::from_ne_bytes([self.data[2], self.data[3]]) & NLA_TYPE_MASK;
+
+        if len < NLA_HDRLEN || len > self.data.len() {
+            log::warn!("skipping malformed nflog attribute");
+            self.data = &[];
+            return None;
+        }
+
+        let value = &self.data[NLA_HDRLEN..len];
+        self.data = self.data.get(align(len)..).unwrap_or_default();
+
+        Some((ty, value))
+    }
+}
+
+/// Builds a NFULNL_MSG_CONFIG message for the given group, containing the given attributes.
+fn config_message(seq: u32, group: u16, attributes: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut message = vec![0u8; NLMSG_HDRLEN];
+
+    message.extend_from_slice(&[libc::AF_UNSPEC as u8, NFNETLINK_V0]);
+    message.extend_from_slice(&group.to_be_bytes());
+
+    for (ty, value) in attributes {
+        let len = NLA_HDRLEN + value.len();
+
+        message.extend_from_slice(&(len as u16).to_ne_bytes());
+        message.extend_from_slice(&ty.to_ne_bytes());
+        message.extend_from_slice(value);
+        message.resize(align(message.len()), 0);
+    }
+
+    let len = message.len() as u32;
+    let ty = (NFNL_SUBSYS_ULOG << 8) | NFULNL_MSG_CONFIG;
+
+    message[0..4].copy_from_slice(&len.to_ne_bytes());
+    message[4..6].copy_from_slice(&ty.to_ne_bytes());
+    message[6..8].copy_from_slice(&(NLM_F_REQUEST | NLM_F_ACK).to_ne_bytes());
+    message[8..12].copy_from_slice(&seq.to_ne_bytes());
+
+    message
+}
+
+/// A netlink socket bound to a NFLOG group.
+pub struct NflogSocket {
+    fd: OwnedFd,
+    buffer: Vec<u8>,
+    seq: u32,
+}
+
+impl NflogSocket {
+    /// Binds to the given NFLOG group and requests copies of the logged packets.
+    pub fn bind(group: u16) -> Result<Self, Error> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_NETFILTER,
+            )
+        };
+
+        if fd < 0 {
+            bail!(
+                "cannot create netfilter netlink socket: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+        let res = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+
+        if res < 0 {
+            bail!(
+                "cannot bind netfilter netlink socket: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        let res = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVBUF,
+                &RECEIVE_BUFFER_SIZE as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+
+        if res < 0 {
+            log::warn!(
+                "cannot set nflog receive buffer size: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        let timeout = libc::timeval {
+            tv_sec: RECEIVE_TIMEOUT.as_secs() as libc::time_t,
+            tv_usec: 0,
+        };
+
+        let res = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+
+        if res < 0 {
+            bail!(
+                "cannot set nflog receive timeout: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        let mut socket = Self {
+            fd,
+            buffer: vec![0u8; 128 * 1024],
+            seq: 0,
+        };
+
+        socket.configure(group, &[(NFULA_CFG_CMD, &[NFULNL_CFG_CMD_BIND])])?;
+
+        // copy the whole packet, the range is a __be32 followed by the mode and padding
+        let mut mode = [0u8; 6];
+        mode[0..4].copy_from_slice(&0xffffu32.to_be_bytes());
+        mode[4] = NFULNL_COPY_PACKET;
+
+        socket.configure(group, &[(NFULA_CFG_MODE, &mode)])?;
+
+        Ok(socket)
+    }
+
+    fn configure(&mut self, group: u16, attributes: &[(u16, &[u8])]) -> Result<(), Error> {
+        self.seq = self.seq.wrapping_add(1);
+        let message = config_message(self.seq, group, attributes);
+
+        let res = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+            )
+        };
+
+        if res < 0 {
+            bail!(
+                "cannot send nflog config message: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        // the group might already be bound, so logged packets can arrive before the
+        // acknowledgement, those are skipped
+        let deadline = Instant::now() + ACK_TIMEOUT;
+
+        while Instant::now() < deadline {
+            let len = match self.recv() {
+                Ok(len) => len,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.raw_os_error() == Some(libc::ENOBUFS) => continue,
+                Err(error) => bail!("cannot receive nflog config acknowledgement: {error}"),
+            };
+
+            if let Some(error) = find_ack(&self.buffer[..len], self.seq)? {
+                if error != 0 {
+                    bail!(
+                        "cannot configure nflog group {group}: {}",
+                        io::Error::from_raw_os_error(-error)
+                    );
+                }
+
+                return Ok(());
+            }
+        }
+
+        bail!("did not receive an acknowledgement for nflog config message")
+    }
+
+    fn recv(&mut self) -> Result<usize, io::Error> {
+        loop {
+            let res = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    self.buffer.as_mut_ptr() as *mut libc::c_void,
+                    self.buffer.len(),
+                    0,
+                )
+            };
+
+            if res >= 0 {
+                return Ok(res as usize);
+            }
+
+            let error = io::Error::last_os_error();
+
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Waits for the next batch of logged packets.
+    ///
+    /// Returns an empty batch if no packet got logged within a second, so callers get a chance to
+    /// check for termination. Packets that could not be delivered because the receive buffer was
+    /// full are lost, this is logged and an empty batch is returned as well.
+    pub fn receive(&mut self) -> Result<Vec<NflogPacket>, Error> {
+        let len = match self.recv() {
+            Ok(len) => len,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(Vec::new()),
+            Err(error) if error.raw_os_error() == Some(libc::ENOBUFS) => {
+                log::warn!("nflog receive buffer overrun, log messages were lost");
+                return Ok(Vec::new());
+            }
+            Err(error) => bail!("cannot receive nflog messages: {error}"),
+        };
+
+        Ok(parse_messages(&self.buffer[..len]))
+    }
+}
+
+/// Looks for the acknowledgement of the message with the given sequence number, returns its
+/// error code.
+fn find_ack(data: &[u8], seq: u32) -> Result<Option<i32>, Error> {
+    for (ty, payload) in Messages::new(data) {
+        if ty != NLMSG_ERROR {
+            continue;
+        }
+
+        // the error code is followed by the header of the acknowledged message
+        let (error, acked_seq) = payload
+            .get(..4)
+            .zip(payload.get(12..16))
+            .map(|(error, acked_seq)| {
+                (
+                    i32::from_ne_bytes(error.try_into().unwrap()),
+                    u32::from_ne_bytes(acked_seq.try_into().unwrap()),
+                )
+            })
+            .ok_or_else(|| format_err!("netlink error message too short"))?;
+
+        if acked_seq == seq {
+            return Ok(Some(error));
+        }
+    }
+
+    Ok(None)
+}
+
+fn parse_messages(data: &[u8]) -> Vec<NflogPacket> {
+    let mut packets = Vec::new();
+
+    for (ty, payload) in Messages::new(data) {
+        if ty != (NFNL_SUBSYS_ULOG << 8) | NFULNL_MSG_PACKET {
+            continue;
+        }
+
+        match NflogPacket::parse(payload) {
+            Ok(packet) => packets.push(packet),
+            Err(error) => log::warn!("cannot parse nflog packet: {error:#}"),
+        }
+    }
+
+    packets
+}
+
+/// Iterator over the netlink messages contained in a buffer, yields type and payload.
+struct Messages<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Messages<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Messages<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < NLMSG_HDRLEN {
+            return None;
+        }
+
+        let len = u32::from_ne_bytes(self.data[0..4].try_into().unwrap()) as usize;
+        let ty = u16::from_ne_bytes([self.data[4], self.data[5]]);
+
+        if len < NLMSG_HDRLEN || len > self.data.len() {
+            log::warn!("skipping malformed netlink message");
+            self.data = &[];
+            return None;
+        }
+
+        let payload = &self.data[NLMSG_HDRLEN..len];
+        self.data = self.data.get(align(len)..).unwrap_or_default();
+
+        Some((ty, payload))
+    }
+}
+
+/// Returns the name of the interface with the given index.
+pub fn interface_name(index: u32) -> Option<String> {
+    let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+
+    let res = unsafe { libc::if_indextoname(index, name.as_mut_ptr()) };
+
+    if res.is_null() {
+        return None;
+    }
+
+    let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(ty: u16, value: &[u8]) -> Vec<u8> {
+        let mut attribute = Vec::new();
+
+        attribute.extend_from_slice(&((NLA_HDRLEN + value.len()) as u16).to_ne_bytes());
+        attribute.extend_from_slice(&ty.to_ne_bytes());
+        attribute.extend_from_slice(value);
+        attribute.resize(align(attribute.len()), 0);
+
+        attribute
+    }
+
+    #[test]
+    fn test_parse_packet_message() {
+        let mut payload = vec![libc::AF_INET as u8, NFNETLINK_V0, 0, 0];
+
+        payload.append(&mut attribute(NFULA_PACKET_HDR, &[0x08, 0x00, 1, 0]));
+        payload.append(&mut attribute(
+            NFULA_PREFIX,
+            b":100:6:guest-100-in: ACCEPT: \0",
+        ));
+        payload.append(&mut attribute(NFULA_IFINDEX_INDEV, &7u32.to_be_bytes()));
+        payload.append(&mut attribute(NFULA_MARK, &42u32.to_be_bytes()));
+        payload.append(&mut attribute(NFULA_PAYLOAD, &[0x45, 0x00, 0x00]));
+
+        let mut timestamp = 1_700_000_000u64.to_be_bytes().to_vec();
+        timestamp.extend_from_slice(&500u64.to_be_bytes());
+        payload.append(&mut attribute(NFULA_TIMESTAMP, &timestamp));
+
+        let mut message = Vec::new();
+        let ty = (NFNL_SUBSYS_ULOG << 8) | NFULNL_MSG_PACKET;
+
+        message.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        message.extend_from_slice(&ty.to_ne_bytes());
+        message.extend_from_slice(&[0; 10]);
+        message.append(&mut payload);
+
+        let packets = parse_messages(&message);
+
+        assert_eq!(
+            packets,
+            vec![NflogPacket {
+                family: libc::AF_INET as u8,
+                hw_protocol: 0x0800,
+                prefix: Some(":100:6:guest-100-in: ACCEPT: ".to_string()),
+                mark: 42,
+                timestamp: Some(
+                    UNIX_EPOCH + Duration::from_secs(1_700_000_000) + Duration::from_micros(500)
+                ),
+                indev: Some(7),
+                payload: vec![0x45, 0x00, 0x00],
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_config_message() {
+        let message = config_message(1, 3, &[(NFULA_CFG_CMD, &[NFULNL_CFG_CMD_BIND])]);
+
+        assert_eq!(message.len(), NLMSG_HDRLEN + NFGENMSG_LEN + 8);
+        assert_eq!(
+            u32::from_ne_bytes(message[0..4].try_into().unwrap()) as usize,
+            message.len()
+        );
+        assert_eq!(&message[NLMSG_HDRLEN + 2..NLMSG_HDRLEN + 4], &[0, 3]);
+        assert_eq!(
+            Attributes::new(&message[NLMSG_HDRLEN + NFGENMSG_LEN..]).collect::<Vec<_>>(),
+            vec![(NFULA_CFG_CMD, &[NFULNL_CFG_CMD_BIND][..])]
+        );
+    }
+
+    #[test]
+    fn test_find_ack() {
+        let ack = |seq: u32, error: i32| {
+            let mut message = Vec::new();
+
+            message.extend_from_slice(&((NLMSG_HDRLEN + 4 + NLMSG_HDRLEN) as u32).to_ne_bytes());
+            message.extend_from_slice(&NLMSG_ERROR.to_ne_bytes());
+            message.extend_from_slice(&[0; 10]);
+            message.extend_from_slice(&error.to_ne_bytes());
+            message.extend_from_slice(&config_message(seq, 0, &[])[..NLMSG_HDRLEN]);
+
+            message
+        };
+
+        // a logged packet and the acknowledgement of an earlier message arrive first
+        let mut data = config_message(2, 0, &[]);
+        let ty = (NFNL_SUBSYS_ULOG << 8) | NFULNL_MSG_PACKET;
+        data[4..6].copy_from_slice(&ty.to_ne_bytes());
+        data.append(&mut ack(1, 0));
+
+        assert_eq!(find_ack(&data, 2).unwrap(), None);
+
+        data.append(&mut ack(2, -libc::EPERM));
+
+        assert_eq!(find_ack(&data, 2).unwrap(), Some(-libc::EPERM));
+        assert_eq!(find_ack(&data, 1).unwrap(), Some(0));
+    }
+}
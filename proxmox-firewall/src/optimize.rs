@@ -0,0 +1,820 @@
+//! Merging of adjacent nftables rules into set and verdict map lookups.
+//!
+//! Every config rule is translated into one or more nftables rules, which get evaluated one after
+//! another for every packet. Runs of adjacent rules that only differ in the values of some of
+//! their matches can be replaced by a single rule that looks those values up in an anonymous set,
+//! a set of concatenations or a verdict map. Only adjacent rules are ever merged, so the first
+//! rule that matches a packet stays the same.
+
+use proxmox_log as log;
+use proxmox_nftables::{
+    Expression, Statement,
+    statement::{Match, Operator, Vmap},
+    types::Verdict,
+};
+
+use crate::rule::NftRule;
+
+/// Merges runs of adjacent rules of a chain into rules doing a set or verdict map lookup.
+pub(crate) fn compress_rules(rules: Vec<NftRule>) -> Vec<NftRule> {
+    let rule_count = rules.len();
+    let mut compressed = Vec::with_capacity(rule_count);
+    let mut run: Option<Run> = None;
+
+    for rule in rules {
+        let rule = match run.as_mut() {
+            Some(run) => match run.try_push(rule) {
+                Ok(()) => continue,
+                Err(rule) => rule,
+            },
+            None => rule,
+        };
+
+        if let Some(run) = run.take() {
+            compressed.append(&mut run.into_rules());
+        }
+
+        if is_mergeable(&rule) {
+            run = Some(Run::new(rule));
+        } else {
+            compressed.push(rule);
+        }
+    }
+
+    if let Some(run) = run {
+        compressed.append(&mut run.into_rules());
+    }
+
+    if compressed.len() != rule_count {
+        log::debug!("compressed {rule_count} rules into {}", compressed.len());
+    }
+
+    compressed
+}
+
+/// A run of adjacent rules that can be merged into a single rule.
+struct Run {
+    rules: Vec<NftRule>,
+    /// positions of the match statements in which the rules differ
+    keys: Vec<usize>,
+    /// whether the rules differ in their verdict as well
+    vmap: bool,
+}
+
+impl Run {
+    fn new(rule: NftRule) -> Self {
+        Self {
+            rules: vec![rule],
+            keys: Vec::new(),
+            vmap: false,
+        }
+    }
+
+    /// Adds the rule to this run, handing it back if it cannot be merged with the run.
+    fn try_push(&mut self, rule: NftRule) -> Result<(), NftRule> {
+        if !is_mergeable(&rule) {
+            return Err(rule);
+        }
+
+        let Some((positions, same_terminal)) = differing_matches(&self.rules[0], &rule) else {
+            return Err(rule);
+        };
+
+        let keys = if self.rules.len() == 1 {
+            if positions.is_empty() {
+                return Err(rule);
+            }
+
+            positions
+        } else if positions
+            .iter()
+            .all(|position| self.keys.contains(position))
+        {
+            self.keys.clone()
+        } else {
+            return Err(rule);
+        };
+
+        let vmap = self.vmap || !same_terminal;
+
+        // the keys and the kind of lookup might have changed with this rule, so check the
+        // existing rules of the run as well
+        let mut rules = self.rules.iter().chain(std::iter::once(&rule));
+
+        let mergeable = if vmap {
+            keys.len() == 1 && rules.all(|rule| is_vmap_element(rule, keys[0]))
+        } else if keys.len() > 1 {
+            rules.all(|rule| {
+                keys.iter()
+                    .all(|key| is_exact_value(match_statement(rule, *key).right()))
+            })
+        } else {
+            rules.all(|rule| is_set_element(rule, keys[0]))
+        };
+
+        if !mergeable
+            || repeats_non_final_value(self.rules.iter().chain(std::iter::once(&rule)), &keys)
+        {
+            return Err(rule);
+        }
+
+        self.keys = keys;
+        self.vmap = vmap;
+        self.rules.push(rule);
+
+        Ok(())
+    }
+
+    fn into_rules(self) -> Vec<NftRule> {
+        if self.rules.len() < 2 {
+            return self.rules;
+        }
+
+        let mut merged = self.rules[0].clone();
+
+        if self.vmap {
+            let key = self.keys[0];
+            let mut values = Vec::new();
+            let mut elements = Vec::new();
+
+            for rule in &self.rules {
+                let value = match_statement(rule, key).right();
+
+                // repeated values are only merged if the first rule for them ends the evaluation,
+                // so the later rules for the value can never match
+                if values.contains(value) {
+                    continue;
+                }
+
+                let Some(Statement::Verdict(verdict)) = rule.terminal_statements().first() else {
+                    unreachable!("checked when adding the rule to the run");
+                };
+
+                values.push(value.clone());
+                elements.push(Expression::List(vec![
+                    value.clone(),
+                    Expression::from(verdict.clone()),
+                ]));
+            }
+
+            let left = match_statement(&merged, key).left().clone();
+
+            merged.remove(key);
+            merged.set_terminal_statements(vec![Vmap::new(left, Expression::set(elements)).into()]);
+        } else if self.keys.len() == 1 {
+            let key = self.keys[0];
+            let mut values = Vec::new();
+
+            for rule in &self.rules {
+                match match_statement(rule, key).right() {
+                    Expression::Set(elements) => values.extend(elements.iter().cloned()),
+                    value => values.push(value.clone()),
+                }
+            }
+
+            dedup(&mut values);
+
+            let left = match_statement(&merged, key).left().clone();
+            merged[key] = Match::new_eq(left, Expression::set(values)).into();
+        } else {
+            let left = Expression::concat(
+                self.keys
+                    .iter()
+                    .map(|key| match_statement(&merged, *key).left().clone()),
+            );
+
+            let mut values: Vec<Expression> = self
+                .rules
+                .iter()
+                .map(|rule| {
+                    Expression::concat(
+                        self.keys
+                            .iter()
+                            .map(|key| match_statement(rule, *key).right().clone()),
+                    )
+                })
+                .collect();
+
+            dedup(&mut values);
+
+            merged[self.keys[0]] = Match::new_eq(left, Expression::set(values)).into();
+
+            for key in self.keys[1..].iter().rev() {
+                merged.remove(*key);
+            }
+        }
+
+        vec![merged]
+    }
+}
+
+/// Only rules consisting of plain matches followed by a single verdict can be merged, anything
+/// else (logging, limits, ...) would change its behavior when being applied once instead of for
+/// every original rule.
+fn is_mergeable(rule: &NftRule) -> bool {
+    rule.iter()
+        .all(|statement| matches!(statement, Statement::Match(_)))
+        && matches!(
+            rule.terminal_statements(),
+            [Statement::Verdict(_) | Statement::Reject(_)]
+        )
+}
+
+/// Returns the positions of the match statements in which the two rules differ, as well as
+/// whether their terminal statements are the same.
+///
+/// Returns [`None`] if the rules differ in anything but the values of equality matches.
+fn differing_matches(first: &NftRule, rule: &NftRule) -> Option<(Vec<usize>, bool)> {
+    if first.family() != rule.family() || first.len() != rule.len() {
+        return None;
+    }
+
+    let mut positions = Vec::new();
+
+    for (position, (a, b)) in first.iter().zip(rule.iter()).enumerate() {
+        if a == b {
+            continue;
+        }
+
+        match (a, b) {
+            (Statement::Match(a), Statement::Match(b))
+                if a.op() == Operator::Eq && b.op() == Operator::Eq && a.left() == b.left() =>
+            {
+                positions.push(position)
+            }
+            _ => return None,
+        }
+    }
+
+    Some((
+        positions,
+        first.terminal_statements() == rule.terminal_statements(),
+    ))
+}
+
+fn match_statement(rule: &NftRule, position: usize) -> &Match {
+    match &rule[position] {
+        Statement::Match(statement) => statement,
+        _ => unreachable!("only match statements are merged"),
+    }
+}
+
+/// Whether the rule can become an element of a verdict map keyed on the match at `position`.
+fn is_vmap_element(rule: &NftRule, position: usize) -> bool {
+    matches!(rule.terminal_statements(), [Statement::Verdict(_)])
+        && is_exact_value(match_statement(rule, position).right())
+}
+
+/// Whether the value matched at `position` can be merged into an anonymous set.
+///
+/// Prefixes and ranges of different rules might overlap, in which case a packet could match more
+/// than one of the original rules. This is only fine if the verdict ends the evaluation of the
+/// chain, since the packet could never reach the second rule then.
+fn is_set_element(rule: &NftRule, position: usize) -> bool {
+    let value = match_statement(rule, position).right();
+
+    if is_exact_value(value) {
+        return true;
+    }
+
+    let is_interval = |value: &Expression| {
+        is_exact_value(value) || matches!(value, Expression::Prefix(_) | Expression::Range(_))
+    };
+
+    let mergeable = match value {
+        Expression::Set(elements) => elements.iter().all(is_interval),
+        value => is_interval(value),
+    };
+
+    mergeable && rule.terminal_statements().iter().all(ends_evaluation)
+}
+
+/// Whether a value is matched by more than one rule of the run, with the first of those rules not
+/// ending the evaluation of the chain.
+///
+/// After merging, the value is only looked up once, so the packet would never reach the later
+/// rules, e.g. when jumping into different group chains for the same interface.
+fn repeats_non_final_value<'a>(
+    rules: impl IntoIterator<Item = &'a NftRule>,
+    keys: &[usize],
+) -> bool {
+    let mut non_final_values = Vec::new();
+
+    for rule in rules {
+        let values = match keys {
+            [key] => match match_statement(rule, *key).right() {
+                Expression::Set(elements) => elements.clone(),
+                value => vec![value.clone()],
+            },
+            keys => vec![Expression::concat(
+                keys.iter()
+                    .map(|key| match_statement(rule, *key).right().clone()),
+            )],
+        };
+
+        if values.iter().any(|value| non_final_values.contains(value)) {
+            return true;
+        }
+
+        if !rule.terminal_statements().iter().all(ends_evaluation) {
+            non_final_values.extend(values);
+        }
+    }
+
+    false
+}
+
+fn is_exact_value(value: &Expression) -> bool {
+    match value {
+        Expression::Number(_) => true,
+        // named sets and interface wildcards cannot be part of an anonymous set
+        Expression::String(value) => !value.starts_with('@') && !value.ends_with('*'),
+        _ => false,
+    }
+}
+
+fn ends_evaluation(statement: &Statement) -> bool {
+    match statement {
+        Statement::Reject(_) => true,
+        Statement::Verdict(Verdict::Continue(_)) => false,
+        // do-reject always ends with a drop, see generate_verdict
+        Statement::Verdict(Verdict::Jump { target }) => target == "do-reject",
+        Statement::Verdict(_) => true,
+        _ => false,
+    }
+}
+
+fn dedup(values: &mut Vec<Expression>) {
+    let mut unique = Vec::with_capacity(values.len());
+
+    for value in values.drain(..) {
+        if !unique.contains(&value) {
+            unique.push(value);
+        }
+    }
+
+    *values = unique;
+}
+
+#[cfg(test)]
+mod tests {
+    use proxmox_nftables::expression::{Meta, Payload, Prefix};
+    use proxmox_nftables::statement::Log;
+    use proxmox_nftables::types::{ChainPart, TableFamily, TablePart};
+    use proxmox_ve_config::firewall::types::Rule;
+    use proxmox_ve_config::firewall::types::rule::Direction;
+
+    use crate::config::FirewallConfig;
+    use crate::config::tests::FixtureLoader;
+    use crate::rule::NftRuleEnv;
+
+    use super::*;
+
+    fn rule(matches: &[(Expression, Expression)], verdict: Statement) -> NftRule {
+        let mut rule = NftRule::new(verdict);
+
+        for (left, right) in matches {
+            rule.push(Match::new_eq(left.clone(), right.clone()).into());
+        }
+
+        rule
+    }
+
+    fn l4proto() -> Expression {
+        Meta::new("l4proto").into()
+    }
+
+    fn dport() -> Expression {
+        Payload::field("th", "dport").into()
+    }
+
+    fn saddr() -> Expression {
+        Payload::field("ip", "saddr").into()
+    }
+
+    fn value_matches(value: &Expression, expected: &Expression) -> bool {
+        match expected {
+            Expression::Set(elements) => elements.contains(value),
+            expected => expected == value,
+        }
+    }
+
+    fn packet_value(packet: &[(Expression, Expression)], field: &Expression) -> Expression {
+        match field {
+            Expression::Concat(fields) => {
+                Expression::concat(fields.iter().map(|field| packet_value(packet, field)))
+            }
+            field => packet
+                .iter()
+                .find(|(key, _)| key == field)
+                .map(|(_, value)| value.clone())
+                .expect("packet has a value for every field"),
+        }
+    }
+
+    /// Returns the verdicts of all rules that match the packet, up to the first one ending the
+    /// evaluation of the chain.
+    fn evaluate(rules: &[NftRule], packet: &[(Expression, Expression)]) -> Vec<Statement> {
+        let mut verdicts = Vec::new();
+
+        for rule in rules {
+            let matches = rule.iter().all(|statement| match statement {
+                Statement::Match(statement) => {
+                    let matches =
+                        value_matches(&packet_value(packet, statement.left()), statement.right());
+
+                    (statement.op() == Operator::Ne) != matches
+                }
+                _ => true,
+            });
+
+            if !matches {
+                continue;
+            }
+
+            let verdict = match rule.terminal_statements() {
+                [Statement::Vmap(vmap)] => {
+                    let Expression::Set(elements) = vmap.data() else {
+                        panic!("vmap data is an anonymous set");
+                    };
+
+                    let value = packet_value(packet, vmap.key());
+
+                    elements.iter().find_map(|element| match element {
+                        Expression::List(element) if element[0] == value => {
+                            let Expression::Verdict(verdict) = &element[1] else {
+                                panic!("vmap element maps to a verdict");
+                            };
+
+                            Some(Statement::Verdict(verdict.clone()))
+                        }
+                        _ => None,
+                    })
+                }
+                terminal_statements => terminal_statements.last().cloned(),
+            };
+
+            if let Some(verdict) = verdict {
+                let ends_evaluation = ends_evaluation(&verdict);
+                verdicts.push(verdict);
+
+                if ends_evaluation {
+                    break;
+                }
+            }
+        }
+
+        verdicts
+    }
+
+    /// Checks that both rule lists return the same verdict for every combination of the given
+    /// field values.
+    fn assert_equivalent(
+        original: &[NftRule],
+        compressed: &[NftRule],
+        fields: &[(Expression, Vec<Expression>)],
+    ) {
+        let mut packets: Vec<Vec<(Expression, Expression)>> = vec![Vec::new()];
+
+        for (field, values) in fields {
+            packets = packets
+                .into_iter()
+                .flat_map(|packet| {
+                    values.iter().map(move |value| {
+                        let mut packet = packet.clone();
+                        packet.push((field.clone(), value.clone()));
+                        packet
+                    })
+                })
+                .collect();
+        }
+
+        for packet in packets {
+            assert_eq!(
+                evaluate(original, &packet),
+                evaluate(compressed, &packet),
+                "different verdict for packet {packet:?}"
+            );
+        }
+    }
+
+    fn ports() -> Vec<Expression> {
+        [21u16, 22, 53, 80, 443, 8006]
+            .into_iter()
+            .map(Expression::from)
+            .collect()
+    }
+
+    fn protocols() -> Vec<Expression> {
+        ["tcp", "udp"].into_iter().map(Expression::from).collect()
+    }
+
+    #[test]
+    fn test_merge_into_set() {
+        let rules: Vec<NftRule> = [22u16, 80, 443]
+            .into_iter()
+            .map(|port| {
+                rule(
+                    &[(l4proto(), "tcp".into()), (dport(), port.into())],
+                    Statement::make_accept(),
+                )
+            })
+            .collect();
+
+        let compressed = compress_rules(rules.clone());
+
+        assert_eq!(compressed.len(), 1);
+        assert_eq!(
+            compressed[0][1],
+            Statement::from(Match::new_eq(
+                dport(),
+                Expression::set([22u16.into(), 80u16.into(), 443u16.into()])
+            ))
+        );
+
+        assert_equivalent(
+            &rules,
+            &compressed,
+            &[(l4proto(), protocols()), (dport(), ports())],
+        );
+    }
+
+    #[test]
+    fn test_merge_into_concatenation() {
+        let rules = vec![
+            rule(
+                &[(l4proto(), "tcp".into()), (dport(), 22u16.into())],
+                Statement::make_accept(),
+            ),
+            rule(
+                &[(l4proto(), "udp".into()), (dport(), 53u16.into())],
+                Statement::make_accept(),
+            ),
+            rule(
+                &[(l4proto(), "tcp".into()), (dport(), 53u16.into())],
+                Statement::make_accept(),
+            ),
+        ];
+
+        let compressed = compress_rules(rules.clone());
+
+        assert_eq!(compressed.len(), 1);
+        assert_eq!(compressed[0].len(), 1);
+
+        assert_equivalent(
+            &rules,
+            &compressed,
+            &[(l4proto(), protocols()), (dport(), ports())],
+        );
+    }
+
+    #[test]
+    fn test_merge_into_vmap() {
+        let rules = vec![
+            rule(&[(dport(), 22u16.into())], Statement::make_accept()),
+            rule(&[(dport(), 80u16.into())], Statement::make_drop()),
+            rule(&[(dport(), 22u16.into())], Statement::make_drop()),
+            rule(&[(dport(), 443u16.into())], Statement::jump("do-reject")),
+        ];
+
+        let compressed = compress_rules(rules.clone());
+
+        assert_eq!(compressed.len(), 1);
+        assert!(compressed[0].is_empty());
+
+        assert_equivalent(&rules, &compressed, &[(dport(), ports())]);
+    }
+
+    #[test]
+    fn test_keep_non_mergeable_rules() {
+        let log_rule =
+            NftRule::from_terminal_statements(vec![Log::new_nflog("prefix".to_string(), 0).into()]);
+
+        let rules = vec![
+            rule(&[(dport(), 22u16.into())], Statement::make_accept()),
+            log_rule,
+            rule(&[(dport(), 80u16.into())], Statement::make_accept()),
+            rule(
+                &[(saddr(), Expression::set_name("v4-dc/management"))],
+                Statement::make_accept(),
+            ),
+            rule(
+                &[(saddr(), Expression::set_name("v4-dc/other"))],
+                Statement::make_accept(),
+            ),
+        ];
+
+        assert_eq!(compress_rules(rules).len(), 5);
+    }
+
+    #[test]
+    fn test_overlapping_values() {
+        let prefix = |address: &str, len| Expression::from(Prefix::new(address, len));
+
+        let accept_rules = vec![
+            rule(
+                &[(saddr(), prefix("10.0.0.0", 8))],
+                Statement::make_accept(),
+            ),
+            rule(
+                &[(saddr(), prefix("10.1.0.0", 16))],
+                Statement::make_accept(),
+            ),
+        ];
+
+        assert_eq!(compress_rules(accept_rules).len(), 1);
+
+        // a packet from 10.1.0.1 jumps into the group chain twice
+        let jump_rules = vec![
+            rule(
+                &[(saddr(), prefix("10.0.0.0", 8))],
+                Statement::jump("group-a-in"),
+            ),
+            rule(
+                &[(saddr(), prefix("10.1.0.0", 16))],
+                Statement::jump("group-a-in"),
+            ),
+        ];
+
+        assert_eq!(compress_rules(jump_rules).len(), 2);
+    }
+
+    /// Checks that the compressed rules of a chain return the same verdicts as the original rules
+    /// for packets built from the values matched by the original rules.
+    fn assert_fixture_equivalent(rules: &[Rule], env: &NftRuleEnv) {
+        let mut original = Vec::new();
+
+        for rule in rules {
+            original.append(&mut NftRule::from_config_rule(rule, env).expect("valid rule"));
+        }
+
+        let compressed = NftRule::from_config_rules(rules, env).expect("valid rules");
+
+        let mut fields: Vec<Expression> = Vec::new();
+
+        for rule in &original {
+            for statement in rule.iter() {
+                if let Statement::Match(statement) = statement
+                    && !fields.contains(statement.left())
+                {
+                    fields.push(statement.left().clone());
+                }
+            }
+        }
+
+        let unmatched = Expression::from("unmatched");
+        let mut packets = vec![
+            fields
+                .iter()
+                .map(|field| (field.clone(), unmatched.clone()))
+                .collect::<Vec<_>>(),
+        ];
+
+        for rule in &original {
+            let mut rule_packets = vec![packets[0].clone()];
+
+            for statement in rule.iter() {
+                let Statement::Match(statement) = statement else {
+                    continue;
+                };
+
+                let values = match statement.right() {
+                    Expression::Set(elements) => elements.clone(),
+                    value => vec![value.clone()],
+                };
+
+                rule_packets = rule_packets
+                    .into_iter()
+                    .flat_map(|packet| {
+                        values.iter().map(move |value| {
+                            let mut packet = packet.clone();
+
+                            for (field, field_value) in packet.iter_mut() {
+                                if field == statement.left() {
+                                    *field_value = value.clone();
+                                }
+                            }
+
+                            packet
+                        })
+                    })
+                    .collect();
+            }
+
+            packets.append(&mut rule_packets);
+        }
+
+        for packet in packets {
+            assert_eq!(
+                evaluate(&original, &packet),
+                evaluate(&compressed, &packet),
+                "different verdict in chain {} for packet {packet:?}",
+                env.chain.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_fixture_equivalence() {
+        let config = FirewallConfig::new(&FixtureLoader, &FixtureLoader).expect("valid fixtures");
+
+        let inet_table = TablePart::new(TableFamily::Inet, "proxmox-firewall");
+        let bridge_table = TablePart::new(TableFamily::Bridge, "proxmox-firewall-guests");
+
+        for direction in [Direction::In, Direction::Out, Direction::Forward] {
+            let env = |table: &TablePart, name: String, vmid| NftRuleEnv {
+                chain: ChainPart::new(table.clone(), name),
+                direction,
+                firewall_config: &config,
+                vmid,
+                compress_rules: true,
+            };
+
+            assert_fixture_equivalent(
+                config.cluster().rules(),
+                &env(&inet_table, format!("cluster-{direction}"), None),
+            );
+
+            assert_fixture_equivalent(
+                config.host().rules(),
+                &env(&inet_table, format!("host-{direction}"), None),
+            );
+
+            for (name, group) in config.cluster().groups() {
+                for table in [&inet_table, &bridge_table] {
+                    assert_fixture_equivalent(
+                        group.rules(),
+                        &env(table, format!("group-{name}-{direction}"), None),
+                    );
+                }
+            }
+
+            if direction == Direction::Forward {
+                continue;
+            }
+
+            for (vmid, guest) in config.guests() {
+                assert_fixture_equivalent(
+                    guest.rules(),
+                    &env(
+                        &bridge_table,
+                        format!("guest-{vmid}-{direction}"),
+                        Some(*vmid),
+                    ),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_repeated_non_final_values() {
+        let iifname = || Expression::from(Meta::new("iifname"));
+
+        // a packet from net0 jumps into both group-a-in and group-c-in
+        let vmap_rules = vec![
+            rule(&[(iifname(), "net0".into())], Statement::jump("group-a-in")),
+            rule(&[(iifname(), "net1".into())], Statement::jump("group-b-in")),
+            rule(&[(iifname(), "net0".into())], Statement::jump("group-c-in")),
+        ];
+
+        let compressed = compress_rules(vmap_rules.clone());
+
+        assert_eq!(compressed.len(), 2);
+        assert_eq!(
+            compressed[1].terminal_statements(),
+            vmap_rules[2].terminal_statements()
+        );
+
+        let interfaces = || {
+            ["net0", "net1", "net2"]
+                .into_iter()
+                .map(Expression::from)
+                .collect()
+        };
+
+        assert_equivalent(&vmap_rules, &compressed, &[(iifname(), interfaces())]);
+
+        let set_rules = vec![
+            rule(&[(iifname(), "net0".into())], Statement::jump("group-a-in")),
+            rule(&[(iifname(), "net1".into())], Statement::jump("group-a-in")),
+            rule(&[(iifname(), "net0".into())], Statement::jump("group-a-in")),
+        ];
+
+        let compressed = compress_rules(set_rules.clone());
+
+        assert_eq!(compressed.len(), 2);
+        assert_equivalent(&set_rules, &compressed, &[(iifname(), interfaces())]);
+
+        // the first rule for net0 ends the evaluation, so the second one can never match
+        let final_rules = vec![
+            rule(&[(iifname(), "net0".into())], Statement::make_accept()),
+            rule(&[(iifname(), "net1".into())], Statement::jump("group-b-in")),
+            rule(&[(iifname(), "net0".into())], Statement::jump("group-c-in")),
+        ];
+
+        let compressed = compress_rules(final_rules.clone());
+
+        assert_eq!(compressed.len(), 1);
+        assert_equivalent(&final_rules, &compressed, &[(iifname(), interfaces())]);
+    }
+}
@@ -0,0 +1,398 @@
+//! Resolution of hostnames used as ipset entries.
+//!
+//! Hostnames get resolved when the firewall configuration is loaded. The answers are cached and
+//! only refreshed once they are older than the configured TTL, so the resolver is not queried on
+//! every update of the firewall rules. If refreshing an answer fails, the stale answer is kept
+//! until the hostname can be resolved again. Failed lookups are retried with an exponential
+//! backoff, so an unresolvable hostname does not block every update. Lookups via the system
+//! resolver are bounded by a timeout, so a hanging DNS server cannot stall an update either.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Error, bail, format_err};
+
+use proxmox_log as log;
+
+/// Default time after which a cached answer gets refreshed.
+pub const DEFAULT_RESOLVER_TTL: Duration = Duration::from_secs(300);
+
+/// Default time after which a failed lookup gets retried for the first time.
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Default time to wait for the system resolver to answer a lookup.
+pub const DEFAULT_RESOLVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Resolves a hostname to the addresses it points to.
+pub trait HostnameResolver {
+    fn resolve(&self, hostname: &str) -> Result<Vec<IpAddr>, Error>;
+}
+
+/// Runs a lookup in a separate thread and waits at most `timeout` for its answer.
+///
+/// A lookup that times out keeps running in the background, its answer is discarded.
+fn resolve_with_timeout<F>(
+    hostname: &str,
+    timeout: Duration,
+    lookup: F,
+) -> Result<Vec<IpAddr>, Error>
+where
+    F: FnOnce(&str) -> Result<Vec<IpAddr>, Error> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let owned_hostname = hostname.to_string();
+
+    thread::Builder::new()
+        .name("resolver".to_string())
+        .spawn(move || {
+            // the receiver is gone if the lookup timed out, so the result can be dropped
+            let _ = sender.send(lookup(&owned_hostname));
+        })
+        .context("unable to spawn resolver thread")?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            bail!("resolving hostname {hostname} timed out after {timeout:?}")
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(format_err!(
+            "resolver thread for hostname {hostname} exited unexpectedly"
+        )),
+    }
+}
+
+/// Resolves hostnames via the resolver of the system.
+#[derive(Debug)]
+pub struct SystemResolver {
+    timeout: Duration,
+}
+
+impl SystemResolver {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl Default for SystemResolver {
+    fn default() -> Self {
+        Self::new(DEFAULT_RESOLVE_TIMEOUT)
+    }
+}
+
+impl HostnameResolver for SystemResolver {
+    fn resolve(&self, hostname: &str) -> Result<Vec<IpAddr>, Error> {
+        resolve_with_timeout(hostname, self.timeout, |hostname| {
+            let mut addresses: Vec<IpAddr> = (hostname, 0)
+                .to_socket_addrs()
+                .with_context(|| format!("unable to resolve hostname {hostname}"))?
+                .map(|socket_addr| socket_addr.ip())
+                .collect();
+
+            addresses.sort();
+            addresses.dedup();
+
+            if addresses.is_empty() {
+                bail!("hostname {hostname} did not resolve to any address");
+            }
+
+            Ok(addresses)
+        })
+    }
+}
+
+/// Resolves hostnames to a fixed set of addresses, for use in tests.
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    entries: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_entry(mut self, hostname: impl Into<String>, addresses: Vec<IpAddr>) -> Self {
+        self.entries.insert(hostname.into(), addresses);
+        self
+    }
+}
+
+impl HostnameResolver for StaticResolver {
+    fn resolve(&self, hostname: &str) -> Result<Vec<IpAddr>, Error> {
+        match self.entries.get(hostname) {
+            Some(addresses) => Ok(addresses.clone()),
+            None => bail!("unable to resolve hostname {hostname}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CachedAnswer {
+    /// The last successfully resolved addresses, if any.
+    addresses: Option<Vec<IpAddr>>,
+    /// Number of failed lookups since the last successful one.
+    failures: u32,
+    refresh_at: Instant,
+}
+
+/// Caches the answers of another [`HostnameResolver`] for the duration of a TTL.
+#[derive(Debug)]
+pub struct CachingResolver<R> {
+    resolver: R,
+    ttl: Duration,
+    retry_interval: Duration,
+    cache: Mutex<HashMap<String, CachedAnswer>>,
+}
+
+impl<R: HostnameResolver> CachingResolver<R> {
+    pub fn new(resolver: R, ttl: Duration) -> Self {
+        Self {
+            resolver,
+            ttl,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the time to wait before retrying a failed lookup.
+    ///
+    /// The interval doubles with every consecutive failure, but never exceeds the TTL.
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    fn retry_delay(&self, failures: u32) -> Duration {
+        self.retry_interval
+            .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+            .min(self.ttl)
+    }
+}
+
+impl<R: HostnameResolver + Default> Default for CachingResolver<R> {
+    fn default() -> Self {
+        Self::new(R::default(), DEFAULT_RESOLVER_TTL)
+    }
+}
+
+impl<R: HostnameResolver> HostnameResolver for CachingResolver<R> {
+    fn resolve(&self, hostname: &str) -> Result<Vec<IpAddr>, Error> {
+        // the lock must not be held while querying the resolver, which can take a while
+        let cached = self.cache.lock().unwrap().get(hostname).cloned();
+
+        if let Some(answer) = &cached
+            && answer.refresh_at > Instant::now()
+        {
+            return match &answer.addresses {
+                Some(addresses) => Ok(addresses.clone()),
+                None => bail!("unable to resolve hostname {hostname}, not retrying yet"),
+            };
+        }
+
+        let result = self.resolver.resolve(hostname);
+        let mut cache = self.cache.lock().unwrap();
+
+        match result {
+            Ok(addresses) => {
+                cache.insert(
+                    hostname.to_string(),
+                    CachedAnswer {
+                        addresses: Some(addresses.clone()),
+                        failures: 0,
+                        refresh_at: Instant::now() + self.ttl,
+                    },
+                );
+
+                Ok(addresses)
+            }
+            Err(err) => {
+                let addresses = cached.as_ref().and_then(|answer| answer.addresses.clone());
+                let failures = cached.map_or(0, |answer| answer.failures) + 1;
+
+                cache.insert(
+                    hostname.to_string(),
+                    CachedAnswer {
+                        addresses: addresses.clone(),
+                        failures,
+                        refresh_at: Instant::now() + self.retry_delay(failures),
+                    },
+                );
+
+                match addresses {
+                    Some(addresses) => {
+                        log::warn!("{err:#}, keeping stale addresses for {hostname}");
+                        Ok(addresses)
+                    }
+                    None => Err(err),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    struct FlakyResolver {
+        answers: RefCell<Vec<Result<Vec<IpAddr>, Error>>>,
+        queries: RefCell<usize>,
+    }
+
+    impl FlakyResolver {
+        fn new(answers: Vec<Result<Vec<IpAddr>, Error>>) -> Self {
+            Self {
+                answers: RefCell::new(answers),
+                queries: RefCell::new(0),
+            }
+        }
+    }
+
+    impl HostnameResolver for FlakyResolver {
+        fn resolve(&self, _hostname: &str) -> Result<Vec<IpAddr>, Error> {
+            *self.queries.borrow_mut() += 1;
+            self.answers.borrow_mut().remove(0)
+        }
+    }
+
+    #[test]
+    fn test_static_resolver() {
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+        let resolver = StaticResolver::new().with_entry("backup.example.com", vec![address]);
+
+        assert_eq!(
+            resolver.resolve("backup.example.com").unwrap(),
+            vec![address]
+        );
+        assert!(resolver.resolve("unknown.example.com").is_err());
+    }
+
+    #[test]
+    fn test_caching_resolver() {
+        let first: IpAddr = "192.0.2.1".parse().unwrap();
+        let second: IpAddr = "192.0.2.2".parse().unwrap();
+
+        let resolver = CachingResolver::new(
+            FlakyResolver::new(vec![Ok(vec![first]), Ok(vec![second])]),
+            Duration::from_secs(3600),
+        );
+
+        assert_eq!(resolver.resolve("backup.example.com").unwrap(), vec![first]);
+        assert_eq!(resolver.resolve("backup.example.com").unwrap(), vec![first]);
+        assert_eq!(*resolver.resolver.queries.borrow(), 1);
+
+        let resolver = CachingResolver::new(
+            FlakyResolver::new(vec![Ok(vec![first]), Ok(vec![second])]),
+            Duration::ZERO,
+        );
+
+        assert_eq!(resolver.resolve("backup.example.com").unwrap(), vec![first]);
+        assert_eq!(
+            resolver.resolve("backup.example.com").unwrap(),
+            vec![second]
+        );
+        assert_eq!(*resolver.resolver.queries.borrow(), 2);
+    }
+
+    #[test]
+    fn test_caching_resolver_keeps_stale_answers() {
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+
+        let resolver = CachingResolver::new(
+            FlakyResolver::new(vec![Ok(vec![address]), Err(anyhow::format_err!("timeout"))]),
+            Duration::ZERO,
+        );
+
+        assert_eq!(
+            resolver.resolve("backup.example.com").unwrap(),
+            vec![address]
+        );
+        assert_eq!(
+            resolver.resolve("backup.example.com").unwrap(),
+            vec![address]
+        );
+
+        let resolver = CachingResolver::new(
+            FlakyResolver::new(vec![Err(anyhow::format_err!("timeout"))]),
+            Duration::ZERO,
+        );
+
+        assert!(resolver.resolve("backup.example.com").is_err());
+    }
+
+    #[test]
+    fn test_caching_resolver_backs_off_after_failures() {
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+
+        let resolver = CachingResolver::new(
+            FlakyResolver::new(vec![Err(anyhow::format_err!("timeout")), Ok(vec![address])]),
+            Duration::from_secs(3600),
+        );
+
+        assert!(resolver.resolve("backup.example.com").is_err());
+        assert!(resolver.resolve("backup.example.com").is_err());
+        assert_eq!(*resolver.resolver.queries.borrow(), 1);
+
+        let resolver = CachingResolver::new(
+            FlakyResolver::new(vec![Err(anyhow::format_err!("timeout")), Ok(vec![address])]),
+            Duration::from_secs(3600),
+        )
+        .with_retry_interval(Duration::ZERO);
+
+        assert!(resolver.resolve("backup.example.com").is_err());
+        assert_eq!(
+            resolver.resolve("backup.example.com").unwrap(),
+            vec![address]
+        );
+        assert_eq!(*resolver.resolver.queries.borrow(), 2);
+    }
+
+    #[test]
+    fn test_resolve_with_timeout() {
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+
+        assert_eq!(
+            resolve_with_timeout("backup.example.com", Duration::from_secs(10), move |_| {
+                Ok(vec![address])
+            })
+            .unwrap(),
+            vec![address]
+        );
+
+        assert!(
+            resolve_with_timeout("backup.example.com", Duration::from_secs(10), |hostname| {
+                bail!("unable to resolve hostname {hostname}")
+            })
+            .is_err()
+        );
+
+        let started = Instant::now();
+
+        let err = resolve_with_timeout("backup.example.com", Duration::from_millis(10), |_| {
+            thread::sleep(Duration::from_secs(5));
+            Ok(Vec::new())
+        })
+        .unwrap_err();
+
+        assert!(err.to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let resolver = CachingResolver::new(StaticResolver::new(), Duration::from_secs(300))
+            .with_retry_interval(Duration::from_secs(10));
+
+        assert_eq!(resolver.retry_delay(1), Duration::from_secs(10));
+        assert_eq!(resolver.retry_delay(2), Duration::from_secs(20));
+        assert_eq!(resolver.retry_delay(3), Duration::from_secs(40));
+        assert_eq!(resolver.retry_delay(10), Duration::from_secs(300));
+        assert_eq!(resolver.retry_delay(u32::MAX), Duration::from_secs(300));
+    }
+}
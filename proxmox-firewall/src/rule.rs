@@ -5,7 +5,7 @@ use anyhow::{Error, bail, format_err};
 use proxmox_log as log;
 use proxmox_nftables::{
     Expression, Statement,
-    expression::{Ct, IpFamily, Meta, Payload, Prefix},
+    expression::{Ct, IpFamily, Meta, Payload, PayloadBase, Prefix},
     statement::{Log, LogLevel, Match, Operator},
     types::{AddRule, ChainPart, SetName, TableFamily, TablePart},
 };
@@ -30,6 +30,8 @@ use proxmox_ve_config::{
 use proxmox_network_types::ip_address::Family;
 
 use crate::config::FirewallConfig;
+use crate::firewall::NFLOG_GROUP;
+use crate::optimize::compress_rules;
 
 #[derive(Debug, Clone)]
 pub(crate) struct NftRule {
@@ -73,12 +75,89 @@ impl NftRule {
         Ok(rules)
     }
 
+    /// Generates the nftables rules for all config rules of a chain.
+    ///
+    /// Unless disabled in the environment, adjacent rules that only differ in the values they
+    /// match on get merged into set or verdict map lookups, see [`compress_rules`].
+    pub fn from_config_rules<'r>(
+        rules: impl IntoIterator<Item = &'r Rule>,
+        env: &NftRuleEnv,
+    ) -> Result<Vec<NftRule>, Error> {
+        let mut nft_rules = Vec::new();
+
+        for rule in rules {
+            nft_rules.append(&mut Self::from_config_rule(rule, env)?);
+        }
+
+        if !env.compress_rules {
+            return Ok(nft_rules);
+        }
+
+        Ok(compress_rules(nft_rules))
+    }
+
+    /// Generates the rules assigning the helper to matching connections.
+    ///
+    /// With `accept`, the matching connections as well as the related connections expected by the
+    /// helper get accepted, otherwise the rules following in the chain decide about them.
     pub fn from_ct_helper(
         ct_helper: &CtHelperMacro,
         env: &NftRuleEnv,
+        accept: bool,
     ) -> Result<Vec<NftRule>, Error> {
         let mut rules = Vec::new();
-        ct_helper.to_nft_rules(&mut rules, env)?;
+
+        if let Some(family) = ct_helper.family()
+            && !env.contains_family(family)
+        {
+            return Ok(rules);
+        }
+
+        if ct_helper.tcp().is_none() && ct_helper.udp().is_none() {
+            return Ok(rules);
+        }
+
+        log::trace!("applying ct helper: {ct_helper:?}");
+
+        let protocols = [
+            (ct_helper.tcp(), ct_helper.tcp_helper_name()),
+            (ct_helper.udp(), ct_helper.udp_helper_name()),
+        ];
+
+        for (protocol, helper_name) in protocols {
+            let Some(protocol) = protocol else {
+                continue;
+            };
+
+            let mut ct_rules = Vec::new();
+
+            if accept {
+                ct_rules.push(NftRule::from_terminal_statements(vec![
+                    Match::new_eq(
+                        Ct::new("state", None),
+                        Expression::List(vec!["new".into(), "established".into()]),
+                    )
+                    .into(),
+                    Statement::make_accept(),
+                ]));
+            }
+
+            ct_rules.push(NftRule::new(Statement::CtHelper(helper_name)));
+
+            protocol.to_nft_rules(&mut ct_rules, env)?;
+            rules.append(&mut ct_rules);
+        }
+
+        if accept {
+            let ip_family = ct_helper.family().map(IpFamily::from);
+            let mut ct_helper_rule = NftRule::new(Statement::make_accept());
+
+            ct_helper_rule
+                .push(Match::new_eq(Ct::new("helper", ip_family), ct_helper.name()).into());
+
+            rules.push(ct_helper_rule);
+        }
+
         Ok(rules)
     }
 
@@ -117,6 +196,14 @@ impl NftRule {
     pub fn set_family(&mut self, family: Family) {
         self.family = Some(family);
     }
+
+    pub fn terminal_statements(&self) -> &[Statement] {
+        &self.terminal_statements
+    }
+
+    pub fn set_terminal_statements(&mut self, terminal_statements: Vec<Statement>) {
+        self.terminal_statements = terminal_statements;
+    }
 }
 
 pub(crate) struct NftRuleEnv<'a> {
@@ -124,6 +211,7 @@ pub(crate) struct NftRuleEnv<'a> {
     pub(crate) direction: Direction,
     pub(crate) firewall_config: &'a FirewallConfig,
     pub(crate) vmid: Option<Vmid>,
+    pub(crate) compress_rules: bool,
 }
 
 impl NftRuleEnv<'_> {
@@ -135,8 +223,19 @@ impl NftRuleEnv<'_> {
         self.firewall_config.alias(name, self.vmid)
     }
 
+    /// Resolves the interface name of a rule.
+    ///
+    /// Host interfaces can be given as a wildcard (e.g. `vmbr*`), which gets passed through to
+    /// nftables as is.
     fn iface_name(&self, rule_iface: &str) -> Result<String, Error> {
+        if rule_iface.is_empty() {
+            bail!("empty interface name in interface list");
+        }
+
         match &self.vmid {
+            Some(vmid) if rule_iface.ends_with('*') => {
+                bail!("cannot use interface wildcard '{rule_iface}' for VM #{vmid}")
+            }
             Some(vmid) => {
                 let config = self
                     .firewall_config
@@ -198,18 +297,29 @@ fn handle_iface(rules: &mut [NftRule], env: &NftRuleEnv, name: &str) -> Result<(
         (Some(_), Direction::Out) => "iifname",
         (None, Direction::In) => "iifname",
         (None, Direction::Out) => "oifname",
-        (_, Direction::Forward) => bail!("cannot define interfaces for forward direction"),
+        (Some(_), Direction::Forward) => bail!("cannot define interfaces for forward direction"),
+        // the bridge table sees the bridge ports as interfaces, match on the bridge instead
+        (None, Direction::Forward) if *env.table().family() == TableFamily::Bridge => "ibrname",
+        (None, Direction::Forward) => "iifname",
     };
 
-    let iface_name = env.iface_name(name)?;
+    let iface_names = name
+        .split(',')
+        .map(|name| env.iface_name(name.trim()))
+        .collect::<Result<Vec<String>, Error>>()?;
+
+    log::trace!("adding interfaces: {iface_names:?}");
 
-    log::trace!("adding interface: {iface_name}");
+    let iface_expression = match iface_names.len() {
+        1 => Expression::from(iface_names.into_iter().next().unwrap()),
+        _ => Expression::set(iface_names.into_iter().map(Expression::from)),
+    };
 
     for rule in rules.iter_mut() {
         rule.push(
             Match::new_eq(
                 Expression::from(Meta::new(iface_key.to_string())),
-                Expression::from(iface_name.clone()),
+                iface_expression.clone(),
             )
             .into(),
         )
@@ -220,10 +330,6 @@ fn handle_iface(rules: &mut [NftRule], env: &NftRuleEnv, name: &str) -> Result<(
 
 impl ToNftRules for RuleGroup {
     fn to_nft_rules(&self, rules: &mut Vec<NftRule>, env: &NftRuleEnv) -> Result<(), Error> {
-        if env.direction == Direction::Forward && self.iface().is_some() {
-            return Ok(());
-        }
-
         let chain_name = format!("group-{}-{}", self.group(), env.direction);
 
         rules.push(NftRule::new(Statement::jump(chain_name)));
@@ -261,7 +367,7 @@ impl ToNftRules for RuleMatch {
                 terminal_statements.push(
                     Log::new_nflog(
                         Log::generate_prefix(env.vmid, log_level, env.chain.name(), self.verdict()),
-                        0,
+                        NFLOG_GROUP.into(),
                     )
                     .into(),
                 );
@@ -400,6 +506,19 @@ fn handle_ipfilter(
     name: &IpsetName,
     field_name: &str,
     env: &NftRuleEnv,
+) -> Result<(), Error> {
+    handle_ipfilter_field(rules, name, env, |family| match family {
+        Family::V4 => Payload::field("ip", field_name),
+        Family::V6 => Payload::field("ip6", field_name),
+    })
+}
+
+/// Like [`handle_ipfilter`], but matches the ipfilter sets against an arbitrary field.
+fn handle_ipfilter_field(
+    rules: &mut Vec<NftRule>,
+    name: &IpsetName,
+    env: &NftRuleEnv,
+    field: impl Fn(Family) -> Payload,
 ) -> Result<(), Error> {
     let mut new_rules = rules
         .drain(..)
@@ -407,7 +526,7 @@ fn handle_ipfilter(
             let mut new_rules = Vec::new();
 
             if matches!(rule.family(), Some(Family::V4) | None) && env.contains_family(Family::V4) {
-                let field = Payload::field("ip", field_name);
+                let field = field(Family::V4);
 
                 let mut match_rule = rule.clone();
                 match_rule.set_family(Family::V4);
@@ -449,7 +568,7 @@ fn handle_ipfilter(
             }
 
             if matches!(rule.family(), Some(Family::V6) | None) && env.contains_family(Family::V6) {
-                let field = Payload::field("ip6", field_name);
+                let field = field(Family::V6);
 
                 let mut match_rule = rule.clone();
                 match_rule.set_family(Family::V6);
@@ -759,6 +878,79 @@ impl ToNftRules for Ports {
     }
 }
 
+/// Converts a MAC address into the number a raw 48 bit payload match compares against.
+fn mac_address_value(mac_address: &str) -> Result<i64, Error> {
+    let octets: Vec<&str> = mac_address.split(':').collect();
+
+    if octets.len() != 6 {
+        bail!("invalid MAC address: {mac_address}");
+    }
+
+    octets.into_iter().try_fold(0i64, |value, octet| {
+        let octet = u8::from_str_radix(octet, 16)
+            .map_err(|err| format_err!("invalid MAC address {mac_address}: {err}"))?;
+
+        Ok((value << 8) | i64::from(octet))
+    })
+}
+
+/// Length of the ICMPv6 payload of a neighbor solicitation or advertisement carrying exactly one
+/// link-layer address option (24 bytes of header and target address, 8 bytes of option).
+const ND_SINGLE_OPTION_PAYLOAD_LEN: i64 = 32;
+
+/// Creates rules dropping neighbor solicitations and advertisements sent from the given interface
+/// that announce a link-layer address other than the given one.
+///
+/// Only the first option, which directly follows the target address, can be checked. Packets
+/// carrying additional options get dropped, so the link-layer address cannot be hidden behind
+/// another option.
+fn nd_link_layer_address_rules(iface_name: String, mac_address: i64) -> Vec<NftRule> {
+    let mut rules = Vec::new();
+
+    for (icmp_type, option_type) in [("nd-neighbor-solicit", 1u8), ("nd-neighbor-advert", 2u8)] {
+        let base_statements = vec![
+            Match::new_eq(Expression::from(Meta::new("iifname")), iface_name.clone()).into(),
+            Match::new_eq(
+                Payload::field("icmpv6", "type"),
+                Expression::from(icmp_type),
+            )
+            .into(),
+        ];
+
+        let mut rule = NftRule::new(Statement::make_drop());
+        rule.set_family(Family::V6);
+        rule.append(&mut base_statements.clone());
+        rule.push(
+            Match::new(
+                Operator::Gt,
+                Payload::field("ip6", "payload_len"),
+                Expression::from(ND_SINGLE_OPTION_PAYLOAD_LEN),
+            )
+            .into(),
+        );
+        rules.push(rule);
+
+        let mut rule = NftRule::new(Statement::make_drop());
+        rule.set_family(Family::V6);
+        rule.append(&mut base_statements.clone());
+        rule.append(&mut vec![
+            Match::new_eq(
+                Payload::raw(PayloadBase::Transport, 192, 8),
+                Expression::from(option_type),
+            )
+            .into(),
+            Match::new_ne(
+                Payload::raw(PayloadBase::Transport, 208, 48),
+                Expression::from(mac_address),
+            )
+            .into(),
+        ]);
+        rules.push(rule);
+    }
+
+    rules
+}
+
 impl ToNftRules for Ipfilter<'_> {
     fn to_nft_rules(&self, rules: &mut Vec<NftRule>, env: &NftRuleEnv) -> Result<(), Error> {
         let vmid = env
@@ -817,6 +1009,9 @@ impl ToNftRules for Ipfilter<'_> {
                 handle_ipfilter(&mut ipfilter_rules, self.ipset().name(), "saddr", env)?;
                 rules.append(&mut ipfilter_rules);
 
+                let mut nd_base_rule = base_rule.clone();
+                nd_base_rule.set_family(Family::V6);
+
                 if env.contains_family(Family::V4) {
                     base_rule.set_family(Family::V4);
 
@@ -835,6 +1030,39 @@ impl ToNftRules for Ipfilter<'_> {
 
                     rules.push(base_rule);
                 }
+
+                if env.contains_family(Family::V6) {
+                    // the IPv6 equivalent of the ARP check above, a guest may only advertise
+                    // addresses from its ipfilter set
+                    nd_base_rule.push(
+                        Match::new_eq(
+                            Payload::field("icmpv6", "type"),
+                            Expression::from("nd-neighbor-advert"),
+                        )
+                        .into(),
+                    );
+
+                    let mut nd_rules = vec![nd_base_rule];
+                    handle_ipfilter_field(&mut nd_rules, self.ipset().name(), env, |_| {
+                        Payload::field("icmpv6", "taddr")
+                    })?;
+                    rules.append(&mut nd_rules);
+                }
+
+                if env.contains_family(Family::V6)
+                    && guest_config.macfilter()
+                    && let Some(device) = guest_config
+                        .network_config()
+                        .network_devices()
+                        .get(&self.index())
+                {
+                    let mac_address = mac_address_value(&device.mac_address().to_string())?;
+
+                    rules.append(&mut nd_link_layer_address_rules(
+                        guest_config.iface_name_by_index(self.index()),
+                        mac_address,
+                    ));
+                }
             }
             Direction::Forward => bail!("cannot generate IP filter for direction forward"),
         }
@@ -842,79 +1070,195 @@ impl ToNftRules for Ipfilter<'_> {
     }
 }
 
-impl ToNftRules for CtHelperMacro {
+impl ToNftRules for FwMacro {
     fn to_nft_rules(&self, rules: &mut Vec<NftRule>, env: &NftRuleEnv) -> Result<(), Error> {
-        if let Some(family) = self.family() {
-            if !env.contains_family(family) {
-                return Ok(());
-            }
-        }
+        log::trace!("applying macro: {self:?}");
 
-        if self.tcp().is_none() && self.udp().is_none() {
-            return Ok(());
+        let initial_rules: Vec<NftRule> = std::mem::take(rules);
+
+        for protocol in &self.code {
+            let mut new_rules = initial_rules.to_vec();
+            protocol.to_nft_rules(&mut new_rules, env)?;
+
+            rules.append(&mut new_rules);
         }
 
-        log::trace!("applying ct helper: {self:?}");
+        Ok(())
+    }
+}
 
-        let ip_family = self.family().map(IpFamily::from);
+#[cfg(test)]
+mod tests {
+    use proxmox_nftables::types::TablePart;
 
-        if let Some(protocol) = self.tcp() {
-            let base_rule = NftRule::from_terminal_statements(vec![
-                Match::new_eq(
-                    Ct::new("state", None),
-                    Expression::List(vec!["new".into(), "established".into()]),
-                )
-                .into(),
-                Statement::make_accept(),
-            ]);
+    use crate::config::tests::FixtureLoader;
 
-            let helper_rule = NftRule::new(Statement::CtHelper(self.tcp_helper_name()));
+    use super::*;
 
-            let mut ct_rules = vec![base_rule, helper_rule];
-            protocol.to_nft_rules(&mut ct_rules, env)?;
-            rules.append(&mut ct_rules);
+    const IFACE_NAME: &str = "tap100i0";
+    const MAC_ADDRESS: [u8; 6] = [0xbc, 0x24, 0x11, 0x00, 0x00, 0x01];
+    const SPOOFED_MAC_ADDRESS: [u8; 6] = [0xbc, 0x24, 0x11, 0x00, 0x00, 0x02];
+
+    /// Builds the ICMPv6 part of a neighbor solicitation carrying the given options.
+    fn neighbor_solicitation(options: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut packet = vec![135, 0, 0, 0, 0, 0, 0, 0];
+        packet.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        for (option_type, data) in options {
+            packet.push(*option_type);
+            packet.push(((data.len() + 2) / 8) as u8);
+            packet.extend_from_slice(data);
         }
 
-        if let Some(protocol) = self.udp() {
-            let base_rule = NftRule::from_terminal_statements(vec![
-                Match::new_eq(
-                    Ct::new("state", None),
-                    Expression::List(vec!["new".into(), "established".into()]),
-                )
-                .into(),
-                Statement::make_accept(),
-            ]);
+        packet
+    }
 
-            let helper_rule = NftRule::new(Statement::CtHelper(self.udp_helper_name()));
+    fn packet_value(packet: &[u8], expression: &Expression) -> Expression {
+        if *expression == Expression::from(Meta::new("iifname")) {
+            return Expression::from(IFACE_NAME);
+        }
 
-            let mut ct_rules = vec![base_rule, helper_rule];
-            protocol.to_nft_rules(&mut ct_rules, env)?;
-            rules.append(&mut ct_rules);
+        if *expression == Expression::from(Payload::field("icmpv6", "type")) {
+            return match packet[0] {
+                135 => Expression::from("nd-neighbor-solicit"),
+                136 => Expression::from("nd-neighbor-advert"),
+                icmp_type => Expression::from(icmp_type),
+            };
         }
 
-        let mut ct_helper_rule = NftRule::new(Statement::make_accept());
+        if *expression == Expression::from(Payload::field("ip6", "payload_len")) {
+            return Expression::from(packet.len() as i64);
+        }
 
-        ct_helper_rule.push(Match::new_eq(Ct::new("helper", ip_family), self.name()).into());
+        for (offset, len) in [(192, 8), (208, 48)] {
+            if *expression == Expression::from(Payload::raw(PayloadBase::Transport, offset, len)) {
+                let bytes = packet
+                    .get((offset / 8) as usize..((offset + len) / 8) as usize)
+                    .unwrap_or_default();
 
-        rules.push(ct_helper_rule);
+                return Expression::from(
+                    bytes
+                        .iter()
+                        .fold(0i64, |value, byte| (value << 8) | i64::from(*byte)),
+                );
+            }
+        }
 
-        Ok(())
+        panic!("unexpected expression {expression:?}");
     }
-}
 
-impl ToNftRules for FwMacro {
-    fn to_nft_rules(&self, rules: &mut Vec<NftRule>, env: &NftRuleEnv) -> Result<(), Error> {
-        log::trace!("applying macro: {self:?}");
+    fn is_dropped(rules: &[NftRule], packet: &[u8]) -> bool {
+        rules.iter().any(|rule| {
+            rule.iter().all(|statement| {
+                let Statement::Match(statement) = statement else {
+                    panic!("unexpected statement {statement:?}");
+                };
 
-        let initial_rules: Vec<NftRule> = std::mem::take(rules);
+                let value = packet_value(packet, statement.left());
 
-        for protocol in &self.code {
-            let mut new_rules = initial_rules.to_vec();
-            protocol.to_nft_rules(&mut new_rules, env)?;
+                match (statement.op(), &value, statement.right()) {
+                    (Operator::Eq, value, right) => value == right,
+                    (Operator::Ne, value, right) => value != right,
+                    (Operator::Gt, Expression::Number(value), Expression::Number(right)) => {
+                        value > right
+                    }
+                    (op, _, _) => panic!("unexpected operator {op:?}"),
+                }
+            })
+        })
+    }
 
-            rules.append(&mut new_rules);
-        }
+    #[test]
+    fn test_nd_link_layer_address_rules() {
+        let mac_address = mac_address_value("bc:24:11:00:00:01").unwrap();
+        let rules = nd_link_layer_address_rules(IFACE_NAME.to_string(), mac_address);
+
+        let nonce: &[u8] = &[1, 2, 3, 4, 5, 6];
+
+        assert!(!is_dropped(&rules, &neighbor_solicitation(&[])));
+        assert!(!is_dropped(&rules, &neighbor_solicitation(&[(14, nonce)])));
+        assert!(!is_dropped(
+            &rules,
+            &neighbor_solicitation(&[(1, &MAC_ADDRESS)])
+        ));
+
+        assert!(is_dropped(
+            &rules,
+            &neighbor_solicitation(&[(1, &SPOOFED_MAC_ADDRESS)])
+        ));
+
+        // the spoofed link-layer address must not be hidden behind another option
+        assert!(is_dropped(
+            &rules,
+            &neighbor_solicitation(&[(14, nonce), (1, &SPOOFED_MAC_ADDRESS)])
+        ));
+        assert!(is_dropped(
+            &rules,
+            &neighbor_solicitation(&[(1, &MAC_ADDRESS), (1, &SPOOFED_MAC_ADDRESS)])
+        ));
+    }
 
-        Ok(())
+    /// Returns the interface match added to a rule for the given interface option.
+    fn iface_match(
+        config: &FirewallConfig,
+        vmid: Option<Vmid>,
+        iface: &str,
+    ) -> Result<(Expression, Expression), Error> {
+        let env = NftRuleEnv {
+            chain: ChainPart::new(
+                TablePart::new(TableFamily::Inet, "proxmox-firewall"),
+                "host-in",
+            ),
+            direction: Direction::In,
+            firewall_config: config,
+            vmid,
+            compress_rules: true,
+        };
+
+        let mut rules = vec![NftRule::new(Statement::make_accept())];
+        handle_iface(&mut rules, &env, iface)?;
+
+        let [Statement::Match(statement)] = rules[0].as_slice() else {
+            panic!("expected a single match statement");
+        };
+
+        Ok((statement.left().clone(), statement.right().clone()))
+    }
+
+    #[test]
+    fn test_iface_lists_and_wildcards() {
+        let iifname = Expression::from(Meta::new("iifname"));
+        let oifname = Expression::from(Meta::new("oifname"));
+
+        let config = FirewallConfig::new(&FixtureLoader, &FixtureLoader).expect("valid fixtures");
+
+        assert_eq!(
+            iface_match(&config, None, "vmbr0").unwrap(),
+            (iifname.clone(), Expression::from("vmbr0"))
+        );
+        assert_eq!(
+            iface_match(&config, None, "vmbr*").unwrap(),
+            (iifname.clone(), Expression::from("vmbr*"))
+        );
+        assert_eq!(
+            iface_match(&config, None, "vmbr0, veth*").unwrap(),
+            (
+                iifname.clone(),
+                Expression::set([Expression::from("vmbr0"), Expression::from("veth*")])
+            )
+        );
+        assert!(iface_match(&config, None, "vmbr0,").is_err());
+
+        let vmid = Some(Vmid::new(100));
+
+        assert_eq!(
+            iface_match(&config, vmid, "net1,net3").unwrap(),
+            (
+                oifname,
+                Expression::set([Expression::from("veth100i1"), Expression::from("veth100i3")])
+            )
+        );
+        assert!(iface_match(&config, vmid, "net*").is_err());
+        assert!(iface_match(&config, vmid, "net1,veth*").is_err());
     }
 }
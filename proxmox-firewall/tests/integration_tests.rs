@@ -1,8 +1,10 @@
-use anyhow::{Context, Error};
+use anyhow::{Context, Error, bail};
 use std::collections::HashMap;
+use std::net::IpAddr;
 
 use proxmox_firewall::config::{FirewallConfig, FirewallConfigLoader, NftConfigLoader};
 use proxmox_firewall::firewall::Firewall;
+use proxmox_firewall::resolver::{HostnameResolver, StaticResolver};
 use proxmox_network_api::AltnameMapping;
 use proxmox_nftables::command::CommandOutput;
 use proxmox_sys::nodename;
@@ -10,11 +12,21 @@ use proxmox_ve_config::guest::types::Vmid;
 use proxmox_ve_config::guest::{GuestEntry, GuestMap, GuestType};
 use proxmox_ve_config::host::types::BridgeName;
 
-struct MockFirewallConfigLoader {}
+struct MockFirewallConfigLoader {
+    resolver: StaticResolver,
+}
 
 impl MockFirewallConfigLoader {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            resolver: StaticResolver::new().with_entry(
+                "backup.example.com",
+                vec![
+                    "192.0.2.20".parse().expect("valid address"),
+                    "2001:db8::20".parse().expect("valid address"),
+                ],
+            ),
+        }
     }
 }
 
@@ -38,6 +50,10 @@ impl FirewallConfigLoader for MockFirewallConfigLoader {
         let entry = GuestEntry::new(hostname, GuestType::Ct);
         map.insert(100.into(), entry);
 
+        // running on another node without a firewall config
+        let entry = GuestEntry::new("other-node".to_string(), GuestType::Ct);
+        map.insert(102.into(), entry);
+
         Ok(GuestMap::from(map))
     }
 
@@ -54,6 +70,10 @@ impl FirewallConfigLoader for MockFirewallConfigLoader {
             return Ok(Some(Box::new(include_str!("input/100.conf").as_bytes())));
         }
 
+        if *vmid == Vmid::new(102) {
+            return Ok(Some(Box::new(include_str!("input/102.conf").as_bytes())));
+        }
+
         Ok(None)
     }
 
@@ -96,6 +116,31 @@ impl FirewallConfigLoader for MockFirewallConfigLoader {
     fn interface_mapping(&self) -> Result<AltnameMapping, Error> {
         Ok(AltnameMapping::from_iter(vec![]))
     }
+
+    fn ipset_file_list(&self) -> Result<Vec<String>, Error> {
+        // all but blocklist.list are ignored, either due to an invalid name or a read error
+        Ok(vec![
+            "blocklist.list".to_string(),
+            "bad name.list".to_string(),
+            "a.b.list".to_string(),
+            "ÿ.list".to_string(),
+            "unreadable.list".to_string(),
+        ])
+    }
+
+    fn ipset_file(&self, file_name: &str) -> Result<Option<Box<dyn std::io::BufRead>>, Error> {
+        if file_name == "unreadable.list" {
+            bail!("permission denied");
+        }
+
+        Ok(Some(Box::new(
+            include_str!("input/blocklist.list").as_bytes(),
+        )))
+    }
+
+    fn resolve_hostname(&self, hostname: &str) -> Result<Vec<IpAddr>, Error> {
+        self.resolver.resolve(hostname)
+    }
 }
 
 struct MockNftConfigLoader {}
@@ -37,16 +37,35 @@ pub enum Command {
     Delete(Delete),
     Flush(Flush),
     List(List),
+    Reset(Reset),
     // Insert(super::Rule),
     // Rename(RenameChain),
     // Replace(super::Rule),
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+/// The objects to list.
+///
+/// Listing a single stateful object requires its name, so unlike before the named objects were
+/// added, this type is no longer `Copy`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum List {
     Chains(Null),
     Sets(Null),
+    Counter(ObjectName),
+    Counters(Null),
+    Quota(ObjectName),
+    Quotas(Null),
+    #[serde(rename = "ct timeout")]
+    CtTimeout(ObjectName),
+    #[serde(rename = "ct expectation")]
+    CtExpectation(ObjectName),
+    Synproxy(ObjectName),
+    Synproxys(Null),
+    Secmark(ObjectName),
+    Secmarks(Null),
+    Flowtable(ObjectName),
+    Flowtables(Null),
 }
 
 impl List {
@@ -59,6 +78,99 @@ impl List {
     pub fn sets() -> Command {
         Command::List(List::Sets(Null))
     }
+
+    #[inline]
+    pub fn counter(counter: impl Into<ObjectName>) -> Command {
+        Command::List(List::Counter(counter.into()))
+    }
+
+    #[inline]
+    pub fn counters() -> Command {
+        Command::List(List::Counters(Null))
+    }
+
+    #[inline]
+    pub fn quota(quota: impl Into<ObjectName>) -> Command {
+        Command::List(List::Quota(quota.into()))
+    }
+
+    #[inline]
+    pub fn quotas() -> Command {
+        Command::List(List::Quotas(Null))
+    }
+
+    #[inline]
+    pub fn ct_timeout(ct_timeout: impl Into<ObjectName>) -> Command {
+        Command::List(List::CtTimeout(ct_timeout.into()))
+    }
+
+    #[inline]
+    pub fn ct_expectation(ct_expectation: impl Into<ObjectName>) -> Command {
+        Command::List(List::CtExpectation(ct_expectation.into()))
+    }
+
+    #[inline]
+    pub fn synproxy(synproxy: impl Into<ObjectName>) -> Command {
+        Command::List(List::Synproxy(synproxy.into()))
+    }
+
+    #[inline]
+    pub fn synproxys() -> Command {
+        Command::List(List::Synproxys(Null))
+    }
+
+    #[inline]
+    pub fn secmark(secmark: impl Into<ObjectName>) -> Command {
+        Command::List(List::Secmark(secmark.into()))
+    }
+
+    #[inline]
+    pub fn secmarks() -> Command {
+        Command::List(List::Secmarks(Null))
+    }
+
+    #[inline]
+    pub fn flowtable(flowtable: impl Into<ObjectName>) -> Command {
+        Command::List(List::Flowtable(flowtable.into()))
+    }
+
+    #[inline]
+    pub fn flowtables() -> Command {
+        Command::List(List::Flowtables(Null))
+    }
+}
+
+/// Resets the state of stateful objects. nftables only supports this for counters and quotas,
+/// the state of every other object type is not resettable.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reset {
+    Counter(ObjectName),
+    Counters(Null),
+    Quota(ObjectName),
+    Quotas(Null),
+}
+
+impl Reset {
+    #[inline]
+    pub fn counter(counter: impl Into<ObjectName>) -> Command {
+        Command::Reset(Reset::Counter(counter.into()))
+    }
+
+    #[inline]
+    pub fn counters() -> Command {
+        Command::Reset(Reset::Counters(Null))
+    }
+
+    #[inline]
+    pub fn quota(quota: impl Into<ObjectName>) -> Command {
+        Command::Reset(Reset::Quota(quota.into()))
+    }
+
+    #[inline]
+    pub fn quotas() -> Command {
+        Command::Reset(Reset::Quotas(Null))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -73,6 +185,15 @@ pub enum Add {
     Element(AddElement),
     #[serde(rename = "ct helper")]
     CtHelper(AddCtHelper),
+    Counter(AddCounter),
+    Quota(AddQuota),
+    #[serde(rename = "ct timeout")]
+    CtTimeout(AddCtTimeout),
+    #[serde(rename = "ct expectation")]
+    CtExpectation(AddCtExpectation),
+    Synproxy(AddSynproxy),
+    Secmark(AddSecmark),
+    Flowtable(AddFlowtable),
 }
 
 impl Add {
@@ -115,6 +236,41 @@ impl Add {
     pub fn ct_helper(ct_helper: impl Into<AddCtHelper>) -> Command {
         Command::Add(Add::CtHelper(ct_helper.into()))
     }
+
+    #[inline]
+    pub fn counter(counter: impl Into<AddCounter>) -> Command {
+        Command::Add(Add::Counter(counter.into()))
+    }
+
+    #[inline]
+    pub fn quota(quota: impl Into<AddQuota>) -> Command {
+        Command::Add(Add::Quota(quota.into()))
+    }
+
+    #[inline]
+    pub fn ct_timeout(ct_timeout: impl Into<AddCtTimeout>) -> Command {
+        Command::Add(Add::CtTimeout(ct_timeout.into()))
+    }
+
+    #[inline]
+    pub fn ct_expectation(ct_expectation: impl Into<AddCtExpectation>) -> Command {
+        Command::Add(Add::CtExpectation(ct_expectation.into()))
+    }
+
+    #[inline]
+    pub fn synproxy(synproxy: impl Into<AddSynproxy>) -> Command {
+        Command::Add(Add::Synproxy(synproxy.into()))
+    }
+
+    #[inline]
+    pub fn secmark(secmark: impl Into<AddSecmark>) -> Command {
+        Command::Add(Add::Secmark(secmark.into()))
+    }
+
+    #[inline]
+    pub fn flowtable(flowtable: impl Into<AddFlowtable>) -> Command {
+        Command::Add(Add::Flowtable(flowtable.into()))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -174,6 +330,17 @@ pub enum Delete {
     Table(TableName),
     Chain(ChainName),
     Set(SetName),
+    Counter(ObjectName),
+    Quota(ObjectName),
+    #[serde(rename = "ct helper")]
+    CtHelper(ObjectName),
+    #[serde(rename = "ct timeout")]
+    CtTimeout(ObjectName),
+    #[serde(rename = "ct expectation")]
+    CtExpectation(ObjectName),
+    Synproxy(ObjectName),
+    Secmark(ObjectName),
+    Flowtable(ObjectName),
 }
 
 impl Delete {
@@ -191,6 +358,46 @@ impl Delete {
     pub fn set(set: impl Into<SetName>) -> Command {
         Command::Delete(Delete::Set(set.into()))
     }
+
+    #[inline]
+    pub fn counter(counter: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::Counter(counter.into()))
+    }
+
+    #[inline]
+    pub fn quota(quota: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::Quota(quota.into()))
+    }
+
+    #[inline]
+    pub fn ct_helper(ct_helper: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::CtHelper(ct_helper.into()))
+    }
+
+    #[inline]
+    pub fn ct_timeout(ct_timeout: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::CtTimeout(ct_timeout.into()))
+    }
+
+    #[inline]
+    pub fn ct_expectation(ct_expectation: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::CtExpectation(ct_expectation.into()))
+    }
+
+    #[inline]
+    pub fn synproxy(synproxy: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::Synproxy(synproxy.into()))
+    }
+
+    #[inline]
+    pub fn secmark(secmark: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::Secmark(secmark.into()))
+    }
+
+    #[inline]
+    pub fn flowtable(flowtable: impl Into<ObjectName>) -> Command {
+        Command::Delete(Delete::Flowtable(flowtable.into()))
+    }
 }
 
 impl From<TableName> for Delete {
@@ -217,6 +424,15 @@ pub enum ListOutput {
     Set(ListSet),
     // Map(super::Map),
     // Element(super::SetElement),
+    Counter(AddCounter),
+    Quota(AddQuota),
+    #[serde(rename = "ct timeout")]
+    CtTimeout(AddCtTimeout),
+    #[serde(rename = "ct expectation")]
+    CtExpectation(AddCtExpectation),
+    Synproxy(AddSynproxy),
+    Secmark(AddSecmark),
+    Flowtable(AddFlowtable),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -231,3 +447,117 @@ impl Deref for CommandOutput {
         &self.nftables
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn table() -> TablePart {
+        TablePart::new(TableFamily::Inet, "proxmox-firewall")
+    }
+
+    #[test]
+    fn test_add_objects() {
+        let mut quota = AddQuota::new(table(), "guest-100", 1024 * 1024);
+        quota.inv = Some(true);
+
+        let commands = Commands::new(vec![
+            Add::counter(AddCounter::new(table(), "dropped")),
+            Add::quota(quota),
+            Add::ct_timeout(
+                AddCtTimeout::new(table(), "tcp-short", CtHelperProtocol::TCP)
+                    .with_timeout("established", 600),
+            ),
+            Add::ct_expectation(AddCtExpectation::new(
+                table(),
+                "ftp-data",
+                CtHelperProtocol::TCP,
+                2021,
+                30000,
+                8,
+            )),
+            Add::synproxy(AddSynproxy::new(table(), "syn-web", 1460, 7)),
+            Add::flowtable(AddFlowtable::new(table(), "fastpath", ["eth0", "vmbr0"])),
+            Add::secmark(AddSecmark::new(
+                table(),
+                "ssh",
+                "system_u:object_r:ssh_server_packet_t:s0",
+            )),
+        ]);
+
+        assert_eq!(
+            serde_json::to_value(&commands).unwrap(),
+            json!({"nftables": [
+                {"add": {"counter": {"family": "inet", "table": "proxmox-firewall", "name": "dropped"}}},
+                {"add": {"quota": {"family": "inet", "table": "proxmox-firewall", "name": "guest-100", "bytes": 1048576, "inv": true}}},
+                {"add": {"ct timeout": {"family": "inet", "table": "proxmox-firewall", "name": "tcp-short", "protocol": "tcp", "policy": {"established": 600}}}},
+                {"add": {"ct expectation": {"family": "inet", "table": "proxmox-firewall", "name": "ftp-data", "protocol": "tcp", "dport": 2021, "timeout": 30000, "size": 8}}},
+                {"add": {"synproxy": {"family": "inet", "table": "proxmox-firewall", "name": "syn-web", "mss": 1460, "wscale": 7}}},
+                {"add": {"flowtable": {"family": "inet", "table": "proxmox-firewall", "name": "fastpath", "hook": "ingress", "prio": 0, "dev": ["eth0", "vmbr0"]}}},
+                {"add": {"secmark": {"family": "inet", "table": "proxmox-firewall", "name": "ssh", "context": "system_u:object_r:ssh_server_packet_t:s0"}}},
+            ]}),
+        );
+    }
+
+    #[test]
+    fn test_object_commands() {
+        let counter = ObjectName::new(table(), "dropped");
+
+        let commands = Commands::new(vec![
+            List::counter(counter.clone()),
+            List::quotas(),
+            Reset::counter(counter.clone()),
+            Reset::quotas(),
+            Delete::counter(counter),
+            Delete::ct_timeout(ObjectName::new(table(), "tcp-short")),
+        ]);
+
+        assert_eq!(
+            serde_json::to_value(&commands).unwrap(),
+            json!({"nftables": [
+                {"list": {"counter": {"family": "inet", "table": "proxmox-firewall", "name": "dropped"}}},
+                {"list": {"quotas": null}},
+                {"reset": {"counter": {"family": "inet", "table": "proxmox-firewall", "name": "dropped"}}},
+                {"reset": {"quotas": null}},
+                {"delete": {"counter": {"family": "inet", "table": "proxmox-firewall", "name": "dropped"}}},
+                {"delete": {"ct timeout": {"family": "inet", "table": "proxmox-firewall", "name": "tcp-short"}}},
+            ]}),
+        );
+    }
+
+    #[test]
+    fn test_list_output_objects() {
+        let output: CommandOutput = serde_json::from_value(json!({"nftables": [
+            {"metainfo": {"version": "1.0.6", "release_name": "Lester Gooch #5", "json_schema_version": 1}},
+            {"counter": {"family": "inet", "name": "dropped", "table": "proxmox-firewall", "handle": 12, "packets": 7, "bytes": 420}},
+            {"quota": {"family": "inet", "name": "guest-100", "table": "proxmox-firewall", "handle": 13, "bytes": 1048576, "used": 2048, "inv": false}},
+            {"ct timeout": {"family": "inet", "name": "tcp-short", "table": "proxmox-firewall", "handle": 14, "protocol": "tcp", "l3proto": "ip", "policy": {"established": 600, "close": 10}}},
+            {"synproxy": {"family": "inet", "name": "syn-web", "table": "proxmox-firewall", "handle": 15, "mss": 1460, "wscale": 7, "flags": ["timestamp", "sack-perm"]}},
+        ]}))
+        .expect("can parse list output");
+
+        let ListOutput::Counter(counter) = &output[1] else {
+            panic!("expected counter, got {:?}", output[1]);
+        };
+        assert_eq!(counter.name(), "dropped");
+        assert_eq!(counter.packets, Some(7));
+        assert_eq!(counter.bytes, Some(420));
+
+        let ListOutput::Quota(quota) = &output[2] else {
+            panic!("expected quota, got {:?}", output[2]);
+        };
+        assert_eq!(quota.used, Some(2048));
+
+        let ListOutput::CtTimeout(ct_timeout) = &output[3] else {
+            panic!("expected ct timeout, got {:?}", output[3]);
+        };
+        assert_eq!(ct_timeout.policy.get("close"), Some(&10));
+
+        let ListOutput::Synproxy(synproxy) = &output[4] else {
+            panic!("expected synproxy, got {:?}", output[4]);
+        };
+        assert_eq!(synproxy.flags.len(), 2);
+    }
+}
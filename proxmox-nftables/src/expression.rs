@@ -1,3 +1,4 @@
+use crate::helper::NfVec;
 use crate::types::{ElemConfig, Verdict};
 use proxmox_ve_config::host::types::BridgeName;
 use serde::{Deserialize, Serialize};
@@ -14,7 +15,7 @@ use proxmox_ve_config::firewall::types::rule_match::{IcmpCode, IcmpType, Icmpv6C
 #[cfg(feature = "config-ext")]
 use proxmox_ve_config::guest::types::Vmid;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Expression {
     Concat(Vec<Expression>),
@@ -26,6 +27,17 @@ pub enum Expression {
     Meta(Meta),
     Ct(Ct),
     Elem(Box<Element>),
+    Fib(Fib),
+    Rt(Rt),
+    Numgen(Numgen),
+    Jhash(Box<Jhash>),
+    Symhash(Symhash),
+    Socket(Socket),
+    Exthdr(Exthdr),
+    #[serde(rename = "tcp option")]
+    TcpOption(TcpOption),
+    Ipsec(Ipsec),
+    Osf(Osf),
 
     #[serde(rename = "|")]
     Or(Box<(Expression, Expression)>),
@@ -140,6 +152,83 @@ impl From<Prefix> for Expression {
     }
 }
 
+impl From<Element> for Expression {
+    #[inline]
+    fn from(element: Element) -> Self {
+        Expression::Elem(Box::new(element))
+    }
+}
+
+impl From<Fib> for Expression {
+    #[inline]
+    fn from(fib: Fib) -> Self {
+        Expression::Fib(fib)
+    }
+}
+
+impl From<Rt> for Expression {
+    #[inline]
+    fn from(rt: Rt) -> Self {
+        Expression::Rt(rt)
+    }
+}
+
+impl From<Numgen> for Expression {
+    #[inline]
+    fn from(numgen: Numgen) -> Self {
+        Expression::Numgen(numgen)
+    }
+}
+
+impl From<Jhash> for Expression {
+    #[inline]
+    fn from(jhash: Jhash) -> Self {
+        Expression::Jhash(Box::new(jhash))
+    }
+}
+
+impl From<Symhash> for Expression {
+    #[inline]
+    fn from(symhash: Symhash) -> Self {
+        Expression::Symhash(symhash)
+    }
+}
+
+impl From<Socket> for Expression {
+    #[inline]
+    fn from(socket: Socket) -> Self {
+        Expression::Socket(socket)
+    }
+}
+
+impl From<Exthdr> for Expression {
+    #[inline]
+    fn from(exthdr: Exthdr) -> Self {
+        Expression::Exthdr(exthdr)
+    }
+}
+
+impl From<TcpOption> for Expression {
+    #[inline]
+    fn from(tcp_option: TcpOption) -> Self {
+        Expression::TcpOption(tcp_option)
+    }
+}
+
+impl From<Ipsec> for Expression {
+    #[inline]
+    fn from(ipsec: Ipsec) -> Self {
+        Expression::Ipsec(ipsec)
+    }
+}
+
+impl From<Osf> for Expression {
+    #[inline]
+    fn from(osf: Osf) -> Self {
+        Expression::Osf(osf)
+    }
+}
+
 impl From<Verdict> for Expression {
     #[inline]
     fn from(value: Verdict) -> Self {
@@ -275,7 +364,7 @@ impl From<Vmid> for Expression {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Meta {
     key: String,
 }
@@ -286,13 +375,13 @@ impl Meta {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Map {
     key: Expression,
     data: Expression,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Ct {
     key: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -336,7 +425,7 @@ impl From<Family> for IpFamily {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Payload {
     Raw(PayloadRaw),
@@ -350,9 +439,14 @@ impl Payload {
             field: field.into(),
         })
     }
+
+    /// Matches `len` bits at bit `offset` relative to the given header.
+    pub fn raw(base: PayloadBase, offset: i64, len: i64) -> Self {
+        Self::Raw(PayloadRaw { base, offset, len })
+    }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum PayloadBase {
     #[serde(rename = "ll")]
     Link,
@@ -362,14 +456,14 @@ pub enum PayloadBase {
     Transport,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct PayloadRaw {
     base: PayloadBase,
     offset: i64,
     len: i64,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct PayloadField {
     protocol: String,
     field: String,
@@ -384,7 +478,7 @@ impl PayloadField {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Prefix {
     addr: Box<Expression>,
     len: u8,
@@ -409,9 +503,366 @@ impl From<&Cidr> for Prefix {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Element {
     #[serde(flatten)]
     config: ElemConfig,
     val: Expression,
 }
+
+impl Element {
+    pub fn new(val: impl Into<Expression>, config: ElemConfig) -> Self {
+        Self {
+            config,
+            val: val.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FibResult {
+    Oif,
+    Oifname,
+    Type,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FibFlag {
+    Saddr,
+    Daddr,
+    Mark,
+    Iif,
+    Oif,
+}
+
+/// Route lookup in the forwarding information base, e.g. for reverse path filtering.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Fib {
+    result: FibResult,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    flags: NfVec<FibFlag>,
+}
+
+impl Fib {
+    pub fn new(result: FibResult, flags: impl IntoIterator<Item = FibFlag>) -> Self {
+        Self {
+            result,
+            flags: NfVec::from_iter(flags),
+        }
+    }
+}
+
+/// Routing data of a packet, e.g. the next hop or the mtu.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Rt {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    family: Option<IpFamily>,
+}
+
+impl Rt {
+    pub fn new(key: impl Into<String>, family: impl Into<Option<IpFamily>>) -> Self {
+        Self {
+            key: key.into(),
+            family: family.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumgenMode {
+    Inc,
+    Random,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Numgen {
+    mode: NumgenMode,
+    #[serde(rename = "mod")]
+    modulus: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<i64>,
+}
+
+impl Numgen {
+    pub fn new(mode: NumgenMode, modulus: i64, offset: impl Into<Option<i64>>) -> Self {
+        Self {
+            mode,
+            modulus,
+            offset: offset.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Jhash {
+    #[serde(rename = "mod")]
+    modulus: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<i64>,
+    expr: Expression,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+
+impl Jhash {
+    pub fn new(expr: impl Into<Expression>, modulus: i64, offset: impl Into<Option<i64>>) -> Self {
+        Self {
+            modulus,
+            offset: offset.into(),
+            expr: expr.into(),
+            seed: None,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Symhash {
+    #[serde(rename = "mod")]
+    modulus: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<i64>,
+}
+
+impl Symhash {
+    pub fn new(modulus: i64, offset: impl Into<Option<i64>>) -> Self {
+        Self {
+            modulus,
+            offset: offset.into(),
+        }
+    }
+}
+
+/// Data of the socket a packet belongs to.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Socket {
+    key: String,
+    /// the cgroupv2 ancestor level, only for the `cgroupv2` key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    level: Option<i64>,
+}
+
+impl Socket {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            level: None,
+        }
+    }
+
+    pub fn cgroupv2(level: i64) -> Self {
+        Self {
+            key: "cgroupv2".to_string(),
+            level: Some(level),
+        }
+    }
+}
+
+/// An IPv6 extension header, without a field it matches on the existence of the header.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Exthdr {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<i64>,
+}
+
+impl Exthdr {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            field: None,
+            offset: None,
+        }
+    }
+
+    pub fn field(name: impl Into<String>, field: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            field: Some(field.into()),
+            offset: None,
+        }
+    }
+}
+
+/// A TCP option, without a field it matches on the existence of the option.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct TcpOption {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+}
+
+impl TcpOption {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            field: None,
+        }
+    }
+
+    pub fn field(name: impl Into<String>, field: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            field: Some(field.into()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpsecDirection {
+    In,
+    Out,
+}
+
+/// Data of the IPsec security association of a packet.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Ipsec {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    family: Option<IpFamily>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spnum: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dir: Option<IpsecDirection>,
+}
+
+impl Ipsec {
+    pub fn new(
+        key: impl Into<String>,
+        family: impl Into<Option<IpFamily>>,
+        dir: impl Into<Option<IpsecDirection>>,
+    ) -> Self {
+        Self {
+            key: key.into(),
+            family: family.into(),
+            spnum: None,
+            dir: dir.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OsfTtl {
+    Loose,
+    Skip,
+}
+
+/// Passive operating system fingerprinting of SYN packets.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Osf {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<OsfTtl>,
+}
+
+impl Osf {
+    pub fn new(key: impl Into<String>, ttl: impl Into<Option<OsfTtl>>) -> Self {
+        Self {
+            key: key.into(),
+            ttl: ttl.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::statement::{Match, Operator};
+
+    /// Deserializes the expression and checks that it serializes to the same JSON again.
+    fn assert_round_trip(value: Value) -> Expression {
+        let expression: Expression =
+            serde_json::from_value(value.clone()).expect("can deserialize expression");
+
+        assert_eq!(
+            serde_json::to_value(&expression).expect("can serialize expression"),
+            value
+        );
+
+        expression
+    }
+
+    #[test]
+    fn test_expression_round_trip() {
+        for value in [
+            json!({"fib": {"result": "oif", "flags": ["saddr", "iif"]}}),
+            json!({"fib": {"result": "type", "flags": "daddr"}}),
+            json!({"rt": {"key": "nexthop", "family": "ip6"}}),
+            json!({"rt": {"key": "mtu"}}),
+            json!({"numgen": {"mode": "inc", "mod": 2, "offset": 100}}),
+            json!({"jhash": {"mod": 4, "expr": {"payload": {"protocol": "ip", "field": "saddr"}}, "seed": 13}}),
+            json!({"symhash": {"mod": 2}}),
+            json!({"socket": {"key": "transparent"}}),
+            json!({"socket": {"key": "cgroupv2", "level": 1}}),
+            json!({"exthdr": {"name": "frag"}}),
+            json!({"exthdr": {"name": "hbh", "field": "nexthdr"}}),
+            json!({"tcp option": {"name": "maxseg", "field": "size"}}),
+            json!({"tcp option": {"name": "sack-perm"}}),
+            json!({"ipsec": {"key": "saddr", "family": "ip", "spnum": 0, "dir": "in"}}),
+            json!({"osf": {"key": "name", "ttl": "loose"}}),
+            json!({"elem": {"val": "10.0.0.1", "timeout": 60, "expires": 30}}),
+            json!({"elem": {"val": 22, "comment": "ssh"}}),
+        ] {
+            assert_round_trip(value);
+        }
+    }
+
+    #[test]
+    fn test_constructors() {
+        assert_eq!(
+            assert_round_trip(json!({"fib": {"result": "oif", "flags": ["saddr", "iif"]}})),
+            Expression::from(Fib::new(FibResult::Oif, [FibFlag::Saddr, FibFlag::Iif]))
+        );
+
+        assert_eq!(
+            assert_round_trip(json!({"elem": {"val": "10.0.0.1", "timeout": 60}})),
+            Expression::from(Element::new("10.0.0.1", ElemConfig::new(60, None, None)))
+        );
+
+        assert_eq!(
+            assert_round_trip(json!({"tcp option": {"name": "maxseg", "field": "size"}})),
+            Expression::from(TcpOption::field("maxseg", "size"))
+        );
+    }
+
+    #[test]
+    fn test_reverse_path_filter() {
+        // fib saddr . iif oif missing
+        let rule = json!({
+            "match": {
+                "op": "==",
+                "left": {"fib": {"result": "oif", "flags": ["saddr", "iif"]}},
+                "right": false
+            }
+        });
+
+        let statement: crate::Statement =
+            serde_json::from_value(rule.clone()).expect("can deserialize rule");
+
+        assert_eq!(
+            statement,
+            Match::new(
+                Operator::Eq,
+                Fib::new(FibResult::Oif, [FibFlag::Saddr, FibFlag::Iif]),
+                false
+            )
+            .into()
+        );
+
+        assert_eq!(serde_json::to_value(&statement).unwrap(), rule);
+    }
+}
@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Null;
 
 impl<'de> Deserialize<'de> for Null {
@@ -35,7 +35,7 @@ impl fmt::Display for Null {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct NfVec<T>(pub(crate) Vec<T>);
 
 impl<T> Default for NfVec<T> {
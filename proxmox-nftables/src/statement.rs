@@ -12,11 +12,12 @@ use proxmox_ve_config::guest::types::Vmid;
 
 use crate::Expression;
 use crate::expression::Ct;
+use crate::expression::IpFamily;
 use crate::expression::Meta;
 use crate::helper::{NfVec, Null};
 use crate::types::{RateTimescale, RateUnit, Verdict};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Statement {
     Match(Match),
@@ -30,6 +31,27 @@ pub enum Statement {
     CtHelper(String),
     Vmap(Vmap),
     Comment(String),
+    #[serde(rename = "ct count")]
+    CtCount(CtCount),
+    #[serde(rename = "ct timeout")]
+    CtTimeout(String),
+    #[serde(rename = "ct expectation")]
+    CtExpectation(String),
+    Secmark(String),
+    Counter(Counter),
+    Quota(Quota),
+    Snat(Nat),
+    Dnat(Nat),
+    Masquerade(Option<Nat>),
+    Redirect(Option<Nat>),
+    Queue(Queue),
+    Dup(Dup),
+    Fwd(Fwd),
+    Meter(Box<Meter>),
+    Synproxy(Synproxy),
+    Tproxy(Tproxy),
+    Xt(Xt),
+    Flow(Flow),
 
     #[serde(untagged)]
     Verdict(Verdict),
@@ -111,6 +133,76 @@ impl From<Log> for Statement {
     }
 }
 
+impl From<CtCount> for Statement {
+    #[inline]
+    fn from(ct_count: CtCount) -> Statement {
+        Statement::CtCount(ct_count)
+    }
+}
+
+impl From<Counter> for Statement {
+    #[inline]
+    fn from(counter: Counter) -> Statement {
+        Statement::Counter(counter)
+    }
+}
+
+impl From<Quota> for Statement {
+    #[inline]
+    fn from(quota: Quota) -> Statement {
+        Statement::Quota(quota)
+    }
+}
+
+impl From<Queue> for Statement {
+    #[inline]
+    fn from(queue: Queue) -> Statement {
+        Statement::Queue(queue)
+    }
+}
+
+impl From<Dup> for Statement {
+    #[inline]
+    fn from(dup: Dup) -> Statement {
+        Statement::Dup(dup)
+    }
+}
+
+impl From<Fwd> for Statement {
+    #[inline]
+    fn from(fwd: Fwd) -> Statement {
+        Statement::Fwd(fwd)
+    }
+}
+
+impl From<Meter> for Statement {
+    #[inline]
+    fn from(meter: Meter) -> Statement {
+        Statement::Meter(Box::new(meter))
+    }
+}
+
+impl From<Synproxy> for Statement {
+    #[inline]
+    fn from(synproxy: Synproxy) -> Statement {
+        Statement::Synproxy(synproxy)
+    }
+}
+
+impl From<Tproxy> for Statement {
+    #[inline]
+    fn from(tproxy: Tproxy) -> Statement {
+        Statement::Tproxy(tproxy)
+    }
+}
+
+impl From<Flow> for Statement {
+    #[inline]
+    fn from(flow: Flow) -> Statement {
+        Statement::Flow(flow)
+    }
+}
+
 impl<T: Into<Limit>> From<T> for Statement {
     #[inline]
     fn from(limit: T) -> Statement {
@@ -129,7 +221,7 @@ impl From<ConfigVerdict> for Statement {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum RejectType {
     #[serde(rename = "tcp reset")]
@@ -139,7 +231,7 @@ pub enum RejectType {
     IcmpV6,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Default, Deserialize, Serialize)]
 pub struct Reject {
     #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
     ty: Option<RejectType>,
@@ -147,7 +239,7 @@ pub struct Reject {
     expr: Option<Expression>,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Log {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -177,13 +269,13 @@ impl Log {
         chain_name: &str,
         verdict: ConfigVerdict,
     ) -> String {
-        format!(
-            ":{}:{}:{}: {}: ",
-            vmid.into().unwrap_or(Vmid::new(0)),
-            log_level.nflog_level(),
-            chain_name,
+        LogPrefix {
+            vmid: vmid.into().unwrap_or(Vmid::new(0)),
+            log_level,
+            chain_name: chain_name.to_string(),
             verdict,
-        )
+        }
+        .to_string()
     }
 
     pub fn new_nflog(prefix: String, group: i64) -> Self {
@@ -195,7 +287,74 @@ impl Log {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+/// The prefix of the log statements generated for firewall rules.
+///
+/// It has the format `:<vmid>:<nflog level>:<chain name>: <verdict>: `, where the VMID is `0` for
+/// rules that do not belong to a guest.
+#[cfg(feature = "config-ext")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogPrefix {
+    pub vmid: Vmid,
+    pub log_level: LogLevel,
+    pub chain_name: String,
+    pub verdict: ConfigVerdict,
+}
+
+#[cfg(feature = "config-ext")]
+impl std::fmt::Display for LogPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            ":{}:{}:{}: {}: ",
+            self.vmid,
+            self.log_level.nflog_level(),
+            self.chain_name,
+            self.verdict,
+        )
+    }
+}
+
+#[cfg(feature = "config-ext")]
+impl std::str::FromStr for LogPrefix {
+    type Err = Error;
+
+    fn from_str(prefix: &str) -> Result<Self, Self::Err> {
+        let Some((vmid, level, rest)) = prefix
+            .strip_prefix(':')
+            .and_then(|prefix| prefix.split_once(':'))
+            .and_then(|(vmid, rest)| {
+                let (level, rest) = rest.split_once(':')?;
+                Some((vmid, level, rest))
+            })
+        else {
+            bail!("invalid log prefix '{prefix}'");
+        };
+
+        let Some((chain_name, verdict)) = rest.split_once(": ") else {
+            bail!("missing verdict in log prefix '{prefix}'");
+        };
+
+        if chain_name.is_empty() {
+            bail!("missing chain name in log prefix '{prefix}'");
+        }
+
+        let verdict = match verdict.strip_suffix(": ").unwrap_or(verdict) {
+            "ACCEPT" => ConfigVerdict::Accept,
+            "REJECT" => ConfigVerdict::Reject,
+            "DROP" => ConfigVerdict::Drop,
+            verdict => bail!("invalid verdict '{verdict}' in log prefix"),
+        };
+
+        Ok(Self {
+            vmid: Vmid::new(vmid.parse()?),
+            log_level: LogLevel::from_nflog_level(level.parse()?)?,
+            chain_name: chain_name.to_string(),
+            verdict,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Emerg,
@@ -242,9 +401,24 @@ impl LogLevel {
             LogLevel::Audit => 7,
         }
     }
+
+    /// Inverse of [`LogLevel::nflog_level`], level `7` is always mapped to [`LogLevel::Debug`].
+    pub fn from_nflog_level(level: u8) -> Result<Self, Error> {
+        Ok(match level {
+            0 => LogLevel::Emerg,
+            1 => LogLevel::Alert,
+            2 => LogLevel::Crit,
+            3 => LogLevel::Err,
+            4 => LogLevel::Warn,
+            5 => LogLevel::Notice,
+            6 => LogLevel::Info,
+            7 => LogLevel::Debug,
+            _ => bail!("invalid nflog level {level}"),
+        })
+    }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogFlag {
     #[serde(rename = "tcp sequence")]
@@ -259,7 +433,7 @@ pub enum LogFlag {
     All,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Limit {
     Named(String),
@@ -272,7 +446,7 @@ impl<T: Into<AnonymousLimit>> From<T> for Limit {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize, Default)]
 pub struct AnonymousLimit {
     pub rate: i64,
 
@@ -305,13 +479,51 @@ impl From<LogRateLimit> for AnonymousLimit {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Limits the number of connections tracked by conntrack.
+///
+/// When used in the statement of a dynamic set, the connections get counted per set element.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct CtCount {
+    pub val: i64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inv: Option<bool>,
+}
+
+impl CtCount {
+    /// Matches if there are more than `val` connections.
+    pub fn over(val: i64) -> Self {
+        Self {
+            val,
+            inv: Some(true),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Vmap {
     key: Expression,
     data: Expression,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+impl Vmap {
+    pub fn new(key: impl Into<Expression>, data: impl Into<Expression>) -> Self {
+        Self {
+            key: key.into(),
+            data: data.into(),
+        }
+    }
+
+    pub fn key(&self) -> &Expression {
+        &self.key
+    }
+
+    pub fn data(&self) -> &Expression {
+        &self.data
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Match {
     op: Operator,
     left: Expression,
@@ -334,9 +546,21 @@ impl Match {
     pub fn new_ne(left: impl Into<Expression>, right: impl Into<Expression>) -> Self {
         Self::new(Operator::Ne, left, right)
     }
+
+    pub fn op(&self) -> Operator {
+        self.op
+    }
+
+    pub fn left(&self) -> &Expression {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Expression {
+        &self.right
+    }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum Operator {
     #[serde(rename = "&")]
     And,
@@ -364,7 +588,7 @@ pub enum Operator {
     In,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Mangle {
     pub key: Expression,
     pub value: Expression,
@@ -386,17 +610,401 @@ impl Mangle {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SetOperation {
     Add,
     Update,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Set {
     pub op: SetOperation,
     pub elem: Expression,
     pub set: String,
     pub stmt: Option<NfVec<Statement>>,
 }
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Counter {
+    Named(String),
+    Anonymous(AnonymousCounter),
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        Counter::Anonymous(AnonymousCounter::default())
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Default, Deserialize, Serialize)]
+pub struct AnonymousCounter {
+    #[serde(default)]
+    pub packets: i64,
+    #[serde(default)]
+    pub bytes: i64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaUnit {
+    Bytes,
+    Kbytes,
+    Mbytes,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Quota {
+    Named(String),
+    Anonymous(AnonymousQuota),
+}
+
+impl<T: Into<AnonymousQuota>> From<T> for Quota {
+    fn from(value: T) -> Self {
+        Quota::Anonymous(value.into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct AnonymousQuota {
+    pub val: i64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub val_unit: Option<QuotaUnit>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_unit: Option<QuotaUnit>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inv: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NatFlag {
+    Random,
+    FullyRandom,
+    Persistent,
+    Netmap,
+}
+
+/// Options of the `snat`, `dnat`, `masquerade` and `redirect` statements.
+///
+/// Masquerade and redirect do not take an address or family.
+#[derive(Clone, Debug, Eq, PartialEq, Default, Deserialize, Serialize)]
+pub struct Nat {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<Expression>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<IpFamily>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<Expression>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: NfVec<NatFlag>,
+}
+
+impl Nat {
+    pub fn new(addr: impl Into<Expression>) -> Self {
+        Self {
+            addr: Some(addr.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_family(mut self, family: IpFamily) -> Self {
+        self.family = Some(family);
+        self
+    }
+
+    pub fn with_port(mut self, port: impl Into<Expression>) -> Self {
+        self.port = Some(port.into());
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueFlag {
+    Bypass,
+    Fanout,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Default, Deserialize, Serialize)]
+pub struct Queue {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num: Option<Expression>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: NfVec<QueueFlag>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Dup {
+    pub addr: Expression,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dev: Option<Expression>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Fwd {
+    pub dev: Expression,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<IpFamily>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<Expression>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Meter {
+    pub name: String,
+    pub key: Expression,
+    pub stmt: Statement,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SynproxyFlag {
+    Timestamp,
+    SackPerm,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Synproxy {
+    Named(String),
+    Anonymous(AnonymousSynproxy),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Default, Deserialize, Serialize)]
+pub struct AnonymousSynproxy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mss: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wscale: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: NfVec<SynproxyFlag>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Default, Deserialize, Serialize)]
+pub struct Tproxy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<IpFamily>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<Expression>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<Expression>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum XtType {
+    Match,
+    Target,
+    Watcher,
+}
+
+/// An iptables extension used by a rule, only ever contained in the output of nftables.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Xt {
+    #[serde(rename = "type")]
+    pub ty: XtType,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowOp {
+    Add,
+}
+
+/// Offloads the connection of the matched packet to a flowtable.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Flow {
+    op: FlowOp,
+    flowtable: String,
+}
+
+impl Flow {
+    pub fn add(flowtable: impl AsRef<str>) -> Self {
+        Self {
+            op: FlowOp::Add,
+            flowtable: format!("@{}", flowtable.as_ref()),
+        }
+    }
+
+    pub fn flowtable(&self) -> &str {
+        self.flowtable.trim_start_matches('@')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    /// Deserializes the statement and checks that it serializes to the same JSON again.
+    fn assert_round_trip(value: Value) -> Statement {
+        let statement: Statement =
+            serde_json::from_value(value.clone()).expect("can deserialize statement");
+
+        assert_eq!(
+            serde_json::to_value(&statement).expect("can serialize statement"),
+            value
+        );
+
+        statement
+    }
+
+    #[test]
+    fn test_statement_round_trip() {
+        for value in [
+            json!({"counter": {"packets": 10, "bytes": 1200}}),
+            json!({"counter": "my-counter"}),
+            json!({"quota": {"val": 25, "val_unit": "mbytes", "used": 1, "used_unit": "kbytes", "inv": true}}),
+            json!({"quota": "my-quota"}),
+            json!({"snat": {"addr": "10.0.0.1", "family": "ip", "port": 1024, "flags": ["random", "persistent"]}}),
+            json!({"dnat": {"addr": {"prefix": {"addr": "10.0.0.0", "len": 24}}, "flags": "netmap"}}),
+            json!({"masquerade": null}),
+            json!({"masquerade": {"port": {"range": [1024, 2048]}, "flags": "fully-random"}}),
+            json!({"redirect": {"port": 8080}}),
+            json!({"queue": {"num": 3, "flags": ["bypass", "fanout"]}}),
+            json!({"dup": {"addr": "10.0.0.2", "dev": "eth0"}}),
+            json!({"fwd": {"dev": "eth1", "family": "ip6", "addr": "fd00::1"}}),
+            json!({"meter": {"name": "ssh", "key": {"payload": {"protocol": "ip", "field": "saddr"}}, "stmt": {"limit": {"rate": 10, "per": "second"}}, "size": 65535}}),
+            json!({"ct count": {"val": 20, "inv": true}}),
+            json!({"synproxy": {"mss": 1460, "wscale": 7, "flags": ["timestamp", "sack-perm"]}}),
+            json!({"synproxy": "my-synproxy"}),
+            json!({"tproxy": {"family": "ip", "addr": "127.0.0.1", "port": 3128}}),
+            json!({"xt": {"type": "match", "name": "conntrack"}}),
+            json!({"ct timeout": "tcp-short"}),
+            json!({"ct expectation": "ftp-data"}),
+            json!({"secmark": "ssh"}),
+            json!({"flow": {"op": "add", "flowtable": "@fastpath"}}),
+        ] {
+            assert_round_trip(value);
+        }
+    }
+
+    #[test]
+    fn test_statement_variants() {
+        assert_eq!(
+            assert_round_trip(json!({"counter": {"packets": 0, "bytes": 0}})),
+            Statement::from(Counter::new())
+        );
+
+        assert_eq!(
+            assert_round_trip(json!({"snat": {"addr": "10.0.0.1", "family": "ip", "port": 1024}})),
+            Statement::Snat(
+                Nat::new("10.0.0.1")
+                    .with_family(IpFamily::Ip)
+                    .with_port(1024u16)
+            )
+        );
+
+        assert_eq!(
+            assert_round_trip(json!({"masquerade": null})),
+            Statement::Masquerade(None)
+        );
+
+        assert_eq!(
+            assert_round_trip(json!({"ct count": {"val": 20, "inv": true}})),
+            Statement::from(CtCount::over(20))
+        );
+
+        let flow = Flow::add("fastpath");
+        assert_eq!(flow.flowtable(), "fastpath");
+        assert_eq!(
+            assert_round_trip(json!({"flow": {"op": "add", "flowtable": "@fastpath"}})),
+            Statement::from(flow)
+        );
+    }
+
+    #[cfg(feature = "config-ext")]
+    #[test]
+    fn test_log_prefix_round_trip() {
+        for (vmid, chain_name) in [
+            (101, "guest-101-in"),
+            (101, "guest-101-out"),
+            (0, "group-x-in"),
+            (0, "group-my-group-forward"),
+            (0, "bridge-vmbr0"),
+            (0, "cluster-in"),
+            (0, "host-forward"),
+        ] {
+            for verdict in [
+                ConfigVerdict::Accept,
+                ConfigVerdict::Reject,
+                ConfigVerdict::Drop,
+            ] {
+                let prefix =
+                    Log::generate_prefix(Vmid::new(vmid), LogLevel::Info, chain_name, verdict);
+
+                assert_eq!(
+                    prefix.parse::<LogPrefix>().expect("valid prefix"),
+                    LogPrefix {
+                        vmid: Vmid::new(vmid),
+                        log_level: LogLevel::Info,
+                        chain_name: chain_name.to_string(),
+                        verdict,
+                    }
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "config-ext")]
+    #[test]
+    fn test_log_prefix_format() {
+        let prefix: LogPrefix = ":100:4:guest-100-in: DROP: ".parse().expect("valid prefix");
+
+        assert_eq!(prefix.vmid, Vmid::new(100));
+        assert_eq!(prefix.log_level, LogLevel::Warn);
+        assert_eq!(prefix.chain_name, "guest-100-in");
+        assert_eq!(prefix.verdict, ConfigVerdict::Drop);
+        assert_eq!(prefix.to_string(), ":100:4:guest-100-in: DROP: ");
+
+        let prefix: LogPrefix = ":0:7:cluster-out: ACCEPT: ".parse().expect("valid prefix");
+        assert_eq!(prefix.log_level, LogLevel::Debug);
+    }
+
+    #[cfg(feature = "config-ext")]
+    #[test]
+    fn test_invalid_log_prefix() {
+        for prefix in [
+            "",
+            "custom: ",
+            ":100:6: ACCEPT: ",
+            ":abc:6:guest-100-in: ACCEPT: ",
+            ":100:8:guest-100-in: ACCEPT: ",
+            ":100:6:guest-100-in: ALLOW: ",
+            ":100:6:guest-100-in",
+        ] {
+            assert!(prefix.parse::<LogPrefix>().is_err(), "parsed '{prefix}'");
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::ops::{Deref, DerefMut};
 
+use crate::expression::Element;
 use crate::expression::IpFamily;
 use crate::helper::{NfVec, Null};
+use crate::statement::{AnonymousCounter, Counter, SynproxyFlag};
 use crate::{Expression, Statement};
 
 use serde::{Deserialize, Serialize};
@@ -56,9 +59,55 @@ pub enum ElementType {
     Ifname,
     Ipv4Addr,
     Ipv6Addr,
+    EtherAddr,
+    InetService,
+    InetProto,
+    Mark,
+    CtState,
+    Verdict,
 }
 proxmox_serde::forward_display_to_serialize!(ElementType);
 
+/// The key or data type of a set or map.
+///
+/// Either a single element type, a concatenation of element types (e.g. `ifname . ifname`) or
+/// the type of an expression (e.g. `typeof oifname`).
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum SetType {
+    #[serde(rename = "typeof")]
+    Typeof(Expression),
+    #[serde(untagged)]
+    Type(NfVec<ElementType>),
+}
+
+impl SetType {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            SetType::Typeof(_) => false,
+            SetType::Type(types) => types.is_empty(),
+        }
+    }
+}
+
+impl Default for SetType {
+    fn default() -> Self {
+        Self::Type(NfVec::new())
+    }
+}
+
+impl From<ElementType> for SetType {
+    #[inline]
+    fn from(value: ElementType) -> Self {
+        Self::Type(NfVec::one(value))
+    }
+}
+
+impl FromIterator<ElementType> for SetType {
+    fn from_iter<I: IntoIterator<Item = ElementType>>(iter: I) -> Self {
+        Self::Type(NfVec::from_iter(iter))
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ChainType {
@@ -82,19 +131,34 @@ pub enum SetFlag {
     Constant,
     Interval,
     Timeout,
+    Dynamic,
 }
 proxmox_serde::forward_display_to_serialize!(SetFlag);
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+/// The data type of a map.
+///
+/// Since it can be derived from an expression via `typeof`, this type is no longer `Copy`.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum OutputType {
     Verdict,
-    Type(ElementType),
+    #[serde(rename = "typeof")]
+    Typeof(Expression),
+    #[serde(untagged)]
+    Type(NfVec<ElementType>),
+}
+
+impl From<ElementType> for OutputType {
+    #[inline]
+    fn from(value: ElementType) -> Self {
+        Self::Type(NfVec::one(value))
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Hook {
+    Ingress,
     Prerouting,
     Input,
     Forward,
@@ -103,7 +167,7 @@ pub enum Hook {
 }
 proxmox_serde::forward_display_to_serialize!(Hook);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Verdict {
     Accept(Null),
@@ -155,14 +219,13 @@ pub enum Priority {
     Number(i64),
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum RateUnit {
     Packets,
     Bytes,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default)]
-#[cfg_attr(test, derive(Eq, PartialEq))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum RateTimescale {
     #[default]
@@ -483,8 +546,8 @@ pub struct SetConfig {
     #[serde(flatten)]
     name: SetName,
 
-    #[serde(rename = "type", default, skip_serializing_if = "Vec::is_empty")]
-    ty: NfVec<ElementType>,
+    #[serde(rename = "type", default, skip_serializing_if = "SetType::is_empty")]
+    ty: SetType,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     policy: Option<SetPolicy>,
@@ -503,19 +566,32 @@ pub struct SetConfig {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     auto_merge: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stmt: Vec<Statement>,
 }
 
 impl SetConfig {
     pub fn new(name: impl Into<SetName>, ty: impl IntoIterator<Item = ElementType>) -> Self {
+        Self::with_type(name, SetType::from_iter(ty))
+    }
+
+    /// Creates a set whose key type is derived from an expression, e.g. `typeof oifname`.
+    pub fn new_typeof(name: impl Into<SetName>, expr: impl Into<Expression>) -> Self {
+        Self::with_type(name, SetType::Typeof(expr.into()))
+    }
+
+    fn with_type(name: impl Into<SetName>, ty: SetType) -> Self {
         Self {
             name: name.into(),
-            ty: NfVec::from_iter(ty),
+            ty,
             flags: Vec::new(),
             policy: None,
             timeout: None,
             gc_interval: None,
             size: None,
             auto_merge: None,
+            stmt: Vec::new(),
         }
     }
 
@@ -532,6 +608,28 @@ impl SetConfig {
         self.auto_merge = Some(auto_merge);
         self
     }
+
+    /// Sets the default timeout of elements in seconds.
+    pub fn with_timeout(mut self, timeout: i64) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_size(mut self, size: i64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: SetPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Attaches a counter to every element of the set.
+    pub fn with_counter(mut self) -> Self {
+        self.stmt.push(Counter::new().into());
+        self
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -547,10 +645,10 @@ pub struct AddMap {
 }
 
 impl AddMap {
-    pub fn new(config: SetConfig, output_type: OutputType) -> Self {
+    pub fn new(config: SetConfig, output_type: impl Into<OutputType>) -> Self {
         Self {
             config,
-            map: output_type,
+            map: output_type.into(),
             elem: NfVec::new(),
         }
     }
@@ -704,6 +802,11 @@ impl MapElem {
     pub fn new(key: Expression, value: impl Into<MapValue>) -> Self {
         Self((key, value.into()))
     }
+
+    /// Creates a map element with a per-element timeout, comment or counter.
+    pub fn with_config(key: Expression, value: impl Into<MapValue>, config: ElemConfig) -> Self {
+        Self((Element::new(key, config).into(), value.into()))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -714,6 +817,15 @@ pub struct AddSetElement {
     elem: Vec<SetElement>,
 }
 
+impl AddSetElement {
+    pub fn new(set: SetName, elem: impl IntoIterator<Item = SetElement>) -> Self {
+        Self {
+            set,
+            elem: Vec::from_iter(elem),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AddMapElement {
     #[serde(flatten)]
@@ -722,6 +834,15 @@ pub struct AddMapElement {
     elem: Vec<MapElement>,
 }
 
+impl AddMapElement {
+    pub fn new(map: SetName, elem: impl IntoIterator<Item = MapElement>) -> Self {
+        Self {
+            map,
+            elem: Vec::from_iter(elem),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum AddElement {
@@ -763,11 +884,16 @@ impl From<AddSetElement> for AddElement {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
 pub struct ElemConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
     timeout: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     expires: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counter: Option<AnonymousCounter>,
 }
 
 impl ElemConfig {
@@ -780,17 +906,41 @@ impl ElemConfig {
             timeout: timeout.into(),
             expires: expires.into(),
             comment: comment.into(),
+            counter: None,
         }
     }
+
+    pub fn with_timeout(mut self, timeout: i64) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Attaches a counter to the element, starting at the given values.
+    pub fn with_counter(mut self, counter: AnonymousCounter) -> Self {
+        self.counter = Some(counter);
+        self
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SetElemObject {
     #[serde(flatten)]
     config: ElemConfig,
+    // older versions serialized the value as "elem", which nftables does not accept
+    #[serde(rename = "val", alias = "elem")]
     elem: SetElem,
 }
 
+/// A map element with its config next to the element.
+///
+/// nftables does not accept this representation, map elements carry their [`ElemConfig`] in the
+/// key instead. Converting it into a [`MapElement`] moves the config there.
+#[deprecated(note = "use MapElem::with_config instead")]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MapElemObject {
     #[serde(flatten)]
@@ -798,10 +948,9 @@ pub struct MapElemObject {
     elem: MapElem,
 }
 
+/// Map elements carry their [`ElemConfig`] in the key, see [`MapElem::with_config`].
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum MapElement {
-    #[serde(rename = "elem")]
-    Object(MapElemObject),
     #[serde(untagged)]
     Value(MapElem),
 }
@@ -812,6 +961,14 @@ impl From<MapElem> for MapElement {
     }
 }
 
+#[allow(deprecated)]
+impl From<MapElemObject> for MapElement {
+    fn from(value: MapElemObject) -> Self {
+        let (key, data) = value.elem.0;
+        Self::Value(MapElem::with_config(key, data, value.config))
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum SetElement {
     #[serde(rename = "elem")]
@@ -820,6 +977,16 @@ pub enum SetElement {
     Value(SetElem),
 }
 
+impl SetElement {
+    /// Creates a set element with a per-element timeout, comment or counter.
+    pub fn with_config(value: impl Into<Expression>, config: ElemConfig) -> Self {
+        Self::Object(SetElemObject {
+            config,
+            elem: SetElem::from(value.into()),
+        })
+    }
+}
+
 impl From<Expression> for SetElement {
     #[inline]
     fn from(value: Expression) -> Self {
@@ -900,6 +1067,353 @@ pub struct AddCtHelper {
     pub l3proto: Option<L3Protocol>,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowtableFlag {
+    /// Offload the connections to the hardware of the devices.
+    Offload,
+}
+
+/// A flowtable for offloading established connections between the given devices.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddFlowtable {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<Handle>,
+
+    hook: Hook,
+    prio: i64,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dev: NfVec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<NfVec<FlowtableFlag>>,
+}
+
+impl AddFlowtable {
+    pub fn new(
+        table: TablePart,
+        name: impl Into<String>,
+        devices: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            table,
+            name: name.into(),
+            handle: None,
+            hook: Hook::Ingress,
+            prio: 0,
+            dev: devices.into_iter().map(Into::into).collect(),
+            flags: None,
+        }
+    }
+
+    pub fn with_priority(mut self, prio: i64) -> Self {
+        self.prio = prio;
+        self
+    }
+
+    pub fn with_flag(mut self, flag: FlowtableFlag) -> Self {
+        self.flags.get_or_insert_with(NfVec::default).push(flag);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn devices(&self) -> &[String] {
+        &self.dev
+    }
+
+    pub fn flags(&self) -> Option<&[FlowtableFlag]> {
+        self.flags.as_deref().map(Vec::as_slice)
+    }
+}
+
+impl From<AddFlowtable> for ObjectName {
+    fn from(value: AddFlowtable) -> Self {
+        Self::new(value.table, value.name)
+    }
+}
+
+/// Identifies a named stateful object (counter, quota, ...) inside a table.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ObjectName {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+}
+
+impl ObjectName {
+    pub fn new(table: TablePart, name: impl Into<String>) -> Self {
+        Self {
+            table,
+            name: name.into(),
+        }
+    }
+
+    pub fn table(&self) -> &TablePart {
+        &self.table
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddCounter {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<Handle>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packets: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<i64>,
+}
+
+impl AddCounter {
+    pub fn new(table: TablePart, name: impl Into<String>) -> Self {
+        Self {
+            table,
+            name: name.into(),
+            handle: None,
+            packets: None,
+            bytes: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<AddCounter> for ObjectName {
+    fn from(value: AddCounter) -> Self {
+        Self::new(value.table, value.name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddQuota {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<Handle>,
+
+    pub bytes: i64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inv: Option<bool>,
+}
+
+impl AddQuota {
+    pub fn new(table: TablePart, name: impl Into<String>, bytes: i64) -> Self {
+        Self {
+            table,
+            name: name.into(),
+            handle: None,
+            bytes,
+            used: None,
+            inv: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<AddQuota> for ObjectName {
+    fn from(value: AddQuota) -> Self {
+        Self::new(value.table, value.name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddCtTimeout {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<Handle>,
+
+    pub protocol: CtHelperProtocol,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l3proto: Option<L3Protocol>,
+
+    /// Timeout in seconds per connection state, e.g. `established` or `close`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub policy: BTreeMap<String, i64>,
+}
+
+impl AddCtTimeout {
+    pub fn new(table: TablePart, name: impl Into<String>, protocol: CtHelperProtocol) -> Self {
+        Self {
+            table,
+            name: name.into(),
+            handle: None,
+            protocol,
+            l3proto: None,
+            policy: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_timeout(mut self, state: impl Into<String>, seconds: i64) -> Self {
+        self.policy.insert(state.into(), seconds);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<AddCtTimeout> for ObjectName {
+    fn from(value: AddCtTimeout) -> Self {
+        Self::new(value.table, value.name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddCtExpectation {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<Handle>,
+
+    pub protocol: CtHelperProtocol,
+    pub dport: u16,
+    /// Timeout in milliseconds.
+    pub timeout: i64,
+    pub size: u8,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l3proto: Option<L3Protocol>,
+}
+
+impl AddCtExpectation {
+    pub fn new(
+        table: TablePart,
+        name: impl Into<String>,
+        protocol: CtHelperProtocol,
+        dport: u16,
+        timeout: i64,
+        size: u8,
+    ) -> Self {
+        Self {
+            table,
+            name: name.into(),
+            handle: None,
+            protocol,
+            dport,
+            timeout,
+            size,
+            l3proto: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<AddCtExpectation> for ObjectName {
+    fn from(value: AddCtExpectation) -> Self {
+        Self::new(value.table, value.name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddSynproxy {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<Handle>,
+
+    pub mss: i64,
+    pub wscale: i64,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: NfVec<SynproxyFlag>,
+}
+
+impl AddSynproxy {
+    pub fn new(table: TablePart, name: impl Into<String>, mss: i64, wscale: i64) -> Self {
+        Self {
+            table,
+            name: name.into(),
+            handle: None,
+            mss,
+            wscale,
+            flags: NfVec::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<AddSynproxy> for ObjectName {
+    fn from(value: AddSynproxy) -> Self {
+        Self::new(value.table, value.name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AddSecmark {
+    #[serde(flatten)]
+    table: TablePart,
+    name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<Handle>,
+
+    pub context: String,
+}
+
+impl AddSecmark {
+    pub fn new(table: TablePart, name: impl Into<String>, context: impl Into<String>) -> Self {
+        Self {
+            table,
+            name: name.into(),
+            handle: None,
+            context: context.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<AddSecmark> for ObjectName {
+    fn from(value: AddSecmark) -> Self {
+        Self::new(value.table, value.name)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ListChain {
     #[serde(flatten)]
@@ -928,3 +1442,205 @@ impl ListSet {
         &self.name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::expression::Meta;
+
+    fn set_name(family: TableFamily, table: &str, name: &str) -> SetName {
+        SetName::new(TablePart::new(family, table), name)
+    }
+
+    #[test]
+    fn test_skeleton_sets() {
+        let synflood = AddSet::from(
+            SetConfig::new(
+                set_name(TableFamily::Inet, "proxmox-firewall", "v4-synflood-limit"),
+                [ElementType::Ipv4Addr],
+            )
+            .with_timeout(60)
+            .with_flag(SetFlag::Dynamic),
+        );
+
+        assert_eq!(
+            serde_json::to_value(&synflood).unwrap(),
+            json!({
+                "family": "inet",
+                "table": "proxmox-firewall",
+                "name": "v4-synflood-limit",
+                "type": "ipv4_addr",
+                "flags": ["dynamic"],
+                "timeout": 60,
+            }),
+        );
+
+        let vm_map_in = AddMap::new(
+            SetConfig::new_typeof(
+                set_name(TableFamily::Bridge, "proxmox-firewall-guests", "vm-map-in"),
+                Meta::new("oifname"),
+            ),
+            OutputType::Verdict,
+        );
+
+        assert_eq!(
+            serde_json::to_value(&vm_map_in).unwrap(),
+            json!({
+                "family": "bridge",
+                "table": "proxmox-firewall-guests",
+                "name": "vm-map-in",
+                "type": {"typeof": {"meta": {"key": "oifname"}}},
+                "map": "verdict",
+            }),
+        );
+
+        let bridge_map = AddMap::new(
+            SetConfig::new(
+                set_name(TableFamily::Bridge, "proxmox-firewall", "bridge-map"),
+                [ElementType::Ifname, ElementType::Ifname],
+            ),
+            OutputType::Verdict,
+        );
+
+        assert_eq!(
+            serde_json::to_value(&bridge_map).unwrap(),
+            json!({
+                "family": "bridge",
+                "table": "proxmox-firewall",
+                "name": "bridge-map",
+                "type": ["ifname", "ifname"],
+                "map": "verdict",
+            }),
+        );
+    }
+
+    #[test]
+    fn test_flowtable_flags() {
+        let flowtable = AddFlowtable::new(
+            TablePart::new(TableFamily::Inet, "proxmox-firewall"),
+            "fastpath",
+            ["eth0", "eth1"],
+        )
+        .with_priority(-100)
+        .with_flag(FlowtableFlag::Offload);
+
+        let value = json!({
+            "family": "inet",
+            "table": "proxmox-firewall",
+            "name": "fastpath",
+            "hook": "ingress",
+            "prio": -100,
+            "dev": ["eth0", "eth1"],
+            "flags": "offload",
+        });
+
+        assert_eq!(serde_json::to_value(&flowtable).unwrap(), value);
+
+        let flowtable: AddFlowtable = serde_json::from_value(value).unwrap();
+        assert_eq!(flowtable.flags(), Some(&[FlowtableFlag::Offload][..]));
+    }
+
+    #[test]
+    fn test_set_types_round_trip() {
+        for value in [
+            json!({"family": "inet", "table": "t", "name": "s", "type": "ether_addr", "stmt": [{"counter": {"packets": 0, "bytes": 0}}]}),
+            json!({"family": "inet", "table": "t", "name": "s", "type": ["inet_proto", "inet_service"], "policy": "memory", "size": 1024}),
+        ] {
+            let set: AddSet = serde_json::from_value(value.clone()).expect("can parse set");
+            assert_eq!(serde_json::to_value(&set).unwrap(), value);
+        }
+
+        for value in [
+            json!({"family": "inet", "table": "t", "name": "m", "type": "mark", "map": ["ipv4_addr", "inet_service"]}),
+            json!({"family": "inet", "table": "t", "name": "m", "type": "ct_state", "map": {"typeof": {"meta": {"key": "iifname"}}}}),
+        ] {
+            let map: AddMap = serde_json::from_value(value.clone()).expect("can parse map");
+            assert_eq!(serde_json::to_value(&map).unwrap(), value);
+        }
+
+        let set = AddSet::from(
+            SetConfig::new(
+                set_name(TableFamily::Inet, "t", "s"),
+                [ElementType::EtherAddr],
+            )
+            .with_counter(),
+        );
+
+        assert_eq!(
+            serde_json::to_value(&set).unwrap()["stmt"],
+            json!([{"counter": {"packets": 0, "bytes": 0}}]),
+        );
+    }
+
+    #[test]
+    fn test_element_config() {
+        let config = ElemConfig::default()
+            .with_timeout(60)
+            .with_comment("guest 100")
+            .with_counter(AnonymousCounter::default());
+
+        let set = AddElement::from(AddSetElement::new(
+            set_name(TableFamily::Inet, "t", "s"),
+            [
+                SetElement::with_config("10.0.0.1", config.clone()),
+                SetElement::from(Expression::from("10.0.0.2")),
+            ],
+        ));
+
+        assert_eq!(
+            serde_json::to_value(&set).unwrap(),
+            json!({
+                "family": "inet",
+                "table": "t",
+                "name": "s",
+                "elem": [
+                    {"elem": {"val": "10.0.0.1", "timeout": 60, "comment": "guest 100", "counter": {"packets": 0, "bytes": 0}}},
+                    "10.0.0.2",
+                ],
+            }),
+        );
+
+        let map = AddElement::from(AddMapElement::new(
+            set_name(TableFamily::Bridge, "t", "m"),
+            [MapElement::Value(MapElem::with_config(
+                Expression::from("tap100i0"),
+                Verdict::Accept(Null),
+                ElemConfig::default().with_timeout(30),
+            ))],
+        ));
+
+        assert_eq!(
+            serde_json::to_value(&map).unwrap(),
+            json!({
+                "family": "bridge",
+                "table": "t",
+                "name": "m",
+                "elem": [[{"elem": {"val": "tap100i0", "timeout": 30}}, {"accept": null}]],
+            }),
+        );
+    }
+
+    #[test]
+    fn test_legacy_element_objects() {
+        let element: SetElement =
+            serde_json::from_value(json!({"elem": {"elem": "10.0.0.1", "timeout": 60}}))
+                .expect("can parse legacy set element");
+
+        assert_eq!(
+            serde_json::to_value(&element).unwrap(),
+            json!({"elem": {"val": "10.0.0.1", "timeout": 60}}),
+        );
+
+        #[allow(deprecated)]
+        let element: MapElemObject =
+            serde_json::from_value(json!({"elem": ["tap100i0", {"accept": null}], "timeout": 30}))
+                .expect("can parse legacy map element");
+
+        assert_eq!(
+            serde_json::to_value(MapElement::from(element)).unwrap(),
+            json!([{"elem": {"val": "tap100i0", "timeout": 30}}, {"accept": null}]),
+        );
+    }
+}